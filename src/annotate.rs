@@ -0,0 +1,138 @@
+use crate::code_container::CodeContainer;
+use crate::comment::Comment;
+use crate::error::Error;
+use crate::executor::{hash, Executor};
+
+const SHEBANG: &str = "#!";
+
+/// How the blocks of an export were selected, for the header.
+#[derive(Debug)]
+pub struct Selection<'a> {
+    /// Source file.
+    pub file: &'a str,
+    pub tag: &'a str,
+    pub executor: &'a str,
+    pub group: Option<&'a str>,
+    /// The blocks were picked one by one with --pick.
+    pub picked: bool,
+}
+
+/// Header of the script with the source, the version, the selection and the
+/// hash of the code, so the script can be compared with the document later.
+fn header(comment: &Comment, selection: &Selection, code: &str) -> Vec<String> {
+    let mut criteria = vec![format!("tag {}", selection.tag), format!("executor {}", selection.executor)];
+    if let Some(group) = selection.group {
+        criteria.push(format!("group {:?}", group));
+    }
+    if selection.picked {
+        criteria.push("picked blocks".into());
+    }
+
+    vec![
+        comment.line(&format!("Exported from {} by eval-md {}", selection.file, env!("CARGO_PKG_VERSION"))),
+        comment.line(&format!("Selection: {}", criteria.join(", "))),
+        comment.line(&format!("Content hash: fnv1a-64 {:016x}", hash(&[code]))),
+    ]
+}
+
+/// Location of the block in the document with the last heading before it,
+/// for example "doc.md:12 (Install)".
+fn location(file: &str, line: usize, headings: &[(usize, String)]) -> String {
+    match headings.iter().rev().find(|(heading, _)| *heading < line) {
+        Some((_, title)) => format!("{}:{} ({})", file, line, title),
+        None => format!("{}:{}", file, line),
+    }
+}
+
+/// The header goes after the shebang, if there is one.
+fn insert_header(script: &str, header: &[String]) -> String {
+    let header = header.join("\n");
+
+    match script.split_once('\n') {
+        Some((shebang, rest)) if shebang.starts_with(SHEBANG) => format!("{}\n{}\n{}", shebang, header, rest),
+        _ if script.starts_with(SHEBANG) => format!("{}\n{}", script, header),
+        _ => format!("{}\n\n{}", header, script),
+    }
+}
+
+/// Exported script with a header and a comment before each block with its
+/// location in the document.
+pub fn export(
+    executor: &dyn Executor,
+    mut content: CodeContainer,
+    selection: &Selection,
+    headings: &[(usize, String)],
+) -> Result<String, Error> {
+    let comment = executor.comment().ok_or_else(|| {
+        Error::Unsupported(format!("--annotate is not supported with {}, it has no comments", executor.binary()))
+    })?;
+
+    let header = header(&comment, selection, &content.lines());
+    content.prepend(|block| comment.line(&location(selection.file, block.line, headings)));
+
+    Ok(insert_header(&executor.export(content), &header))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::executor::language_picker;
+
+    use super::*;
+
+    #[test]
+    fn test_location() {
+        let headings: Vec<(usize, String)> = vec![(1, "Usage".into()), (10, "Install".into())];
+
+        let test_cases: Vec<(usize, &str)> = vec![
+            (5, "doc.md:5 (Usage)"),
+            (12, "doc.md:12 (Install)"),
+            (10, "doc.md:10 (Usage)"),
+            (1, "doc.md:1"),
+        ];
+
+        for case in test_cases {
+            assert_eq!(location("doc.md", case.0, &headings), case.1);
+        }
+    }
+
+    #[test]
+    fn test_insert_header() {
+        let header: Vec<String> = vec!["# a".into(), "# b".into()];
+
+        let test_cases: Vec<(&str, &str)> = vec![
+            ("#!/bin/sh\n\necho", "#!/bin/sh\n# a\n# b\n\necho"),
+            ("#!/bin/sh", "#!/bin/sh\n# a\n# b"),
+            ("echo", "# a\n# b\n\necho"),
+        ];
+
+        for case in test_cases {
+            assert_eq!(insert_header(case.0, &header), case.1);
+        }
+    }
+
+    #[test]
+    fn test_header() {
+        let selection = Selection { file: "doc.md", tag: "py", executor: "python", group: Some("setup"), picked: true };
+        let header = header(&Comment::line_prefix("#"), &selection, "print(1)");
+
+        assert_eq!(header[0], format!("# Exported from doc.md by eval-md {}", env!("CARGO_PKG_VERSION")));
+        assert_eq!(header[1], "# Selection: tag py, executor python, group \"setup\", picked blocks");
+        assert_eq!(header[2], format!("# Content hash: fnv1a-64 {:016x}", hash(&["print(1)"])));
+    }
+
+    #[test]
+    fn test_export() {
+        let mut content = CodeContainer::new();
+        content.open_block(3, "sql", vec![]);
+        content.push("select 1;".into());
+        content.close_group();
+
+        let selection = Selection { file: "doc.md", tag: "sql", executor: "sqlite3", group: None, picked: false };
+        let sql = language_picker("sqlite3").unwrap();
+        let script = export(sql.as_ref(), content.clone(), &selection, &[(1, "Query".into())]).unwrap();
+        assert!(script.ends_with("-- doc.md:3 (Query)\nselect 1;"));
+
+        let php = language_picker("php").unwrap();
+        assert!(export(php.as_ref(), content, &selection, &[]).is_err());
+    }
+}
@@ -1,8 +1,23 @@
 use std::str::FromStr;
 
+use crate::error::Error;
+
 const CB_OPTION_GROUP: &str = "group";
+pub const CB_OPTION_TIMEOUT: &str = "timeout";
+pub const CB_OPTION_ENV: &str = "env";
+pub const CB_OPTION_CWD: &str = "cwd";
+pub const CB_OPTION_MODULE: &str = "module";
+pub const CB_OPTION_DB: &str = "db";
+pub const CB_OPTION_VENV: &str = "venv";
+pub const CB_OPTION_EXPECT: &str = "expect";
+pub const CB_OPTION_EXIT: &str = "exit";
+pub const CB_OPTION_STDERR_CONTAINS: &str = "stderr-contains";
+pub const CB_OPTION_STDOUT_MATCHES: &str = "stdout-matches";
+pub const CB_OPTION_PROMPT: &str = "prompt";
+pub const CB_OPTION_FILE: &str = "file";
+pub const CB_OPTION_MODE: &str = "mode";
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub struct CodeBlockOption {
     pub key: String,
     pub value: String,
@@ -13,28 +28,56 @@ impl CodeBlockOption {
         self.key == CB_OPTION_GROUP
     }
 
+    /// Options after the first '#' of the fence line, separated by spaces.
+    /// A value can be quoted to keep its spaces, for example:
+    /// `#stderr-contains="not found"`.
     pub fn parse_options(line: &str) -> Vec<CodeBlockOption> {
-        if !line.contains('#') {
+        let Some((_, options)) = line.split_once('#') else {
             return vec![]
+        };
+
+        split_words(options)
+            .iter()
+            .filter_map(|x| CodeBlockOption::from_str(x.trim_start_matches('#')).ok())
+            .collect()
+    }
+}
+
+/// Splits the text on whitespace, except in quoted values. A quote starts a
+/// value at the beginning of a word or after '=', and `\"` is a quote in it.
+pub fn split_words(text: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut quote: Option<char> = None;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match quote {
+            Some(q) if c == '\\' && chars.peek() == Some(&q) => word.extend(chars.next()),
+            Some(q) if c == q => quote = None,
+            Some(_) => word.push(c),
+            None if (c == '"' || c == '\'') && (word.is_empty() || word.ends_with('=')) => quote = Some(c),
+            None if c.is_whitespace() => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            },
+            None => word.push(c),
         }
-        let parts = line.split('#');
-        if let Some(options) = parts.last() {
-            let options = options.split(' ')
-                          .filter_map(|x| CodeBlockOption::from_str(x).ok())
-                          .collect::<Vec<CodeBlockOption>>();
-            options
-        } else {
-            vec![]
-        }
     }
+    if !word.is_empty() {
+        words.push(word);
+    }
+
+    words
 }
 
 impl std::str::FromStr for CodeBlockOption {
-    type Err = String;
+    type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         if s.is_empty() {
-            return Err("empty string found for options list".into())
+            return Err(Error::Parse("empty string found for options list".into()))
         }
         if !s.contains('=') {
             return Ok(CodeBlockOption{key: s.into(), value: "".into()})
@@ -57,6 +100,13 @@ pub fn find_group_name(options: Vec::<CodeBlockOption>) -> String {
     res.first().cloned().unwrap_or_default()
 }
 
+pub fn find_option(options: &[CodeBlockOption], key: &str) -> Option<String> {
+    options
+        .iter()
+        .find(|x| x.key == key)
+        .map(|x| x.value.clone())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -89,6 +139,10 @@ mod tests {
             ("```bash #group=a", vec![new_cbo("group", "a")]),
             ("```bash #group=a version=3", vec![new_cbo("group", "a"), new_cbo("version", "3")]),
             ("```bash # group=a", vec![new_cbo("group", "a")]),
+            ("```bash #group=a #timeout=5s", vec![new_cbo("group", "a"), new_cbo("timeout", "5s")]),
+            ("```bash #stderr-contains=\"not found\" exit=1", vec![new_cbo("stderr-contains", "not found"), new_cbo("exit", "1")]),
+            ("```bash #stdout-matches='^\\d+ #items$'", vec![new_cbo("stdout-matches", "^\\d+ #items$")]),
+            ("```bash #title=\"say \\\"hi\\\"\" msg=don't", vec![new_cbo("title", "say \"hi\""), new_cbo("msg", "don't")]),
         ];
 
         for case in test_cases {
@@ -96,4 +150,13 @@ mod tests {
             assert_eq!(result, case.1);
         }
     }
+
+    #[test]
+    fn test_find_option() {
+        let options = vec![new_cbo("group", "a"), new_cbo("timeout", "3s"), new_cbo("timeout", "5s")];
+
+        assert_eq!(find_option(&options, "timeout"), Some("3s".into()));
+        assert_eq!(find_option(&options, "group"), Some("a".into()));
+        assert_eq!(find_option(&options, "nope"), None);
+    }
 }
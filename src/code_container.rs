@@ -1,7 +1,34 @@
-#[derive(Debug)]
+use crate::code_block_options::{CodeBlockOption, find_option};
+
+#[derive(Debug, Default, Clone)]
+pub struct Block {
+    pub lines: Vec<String>,
+    /// Line number of the opening fence in the source file (1-based).
+    pub line: usize,
+    /// Language tag of the fence.
+    pub tag: String,
+    pub options: Vec<CodeBlockOption>,
+}
+
+impl Block {
+    pub fn option(&self, key: &str) -> Option<String> {
+        find_option(&self.options, key)
+    }
+
+    /// All values of an option that can be defined more than once.
+    pub fn option_values(&self, key: &str) -> Vec<String> {
+        self.options
+            .iter()
+            .filter(|x| x.key == key)
+            .map(|x| x.value.clone())
+            .collect()
+    }
+}
+
+#[derive(Debug, Default, Clone)]
 pub struct CodeContainer {
-    blocks: Vec<Vec<String>>,
-    open: Option<Vec<String>>,
+    blocks: Vec<Block>,
+    open: Option<Block>,
 }
 
 impl CodeContainer {
@@ -9,8 +36,13 @@ impl CodeContainer {
         Self{ blocks: vec![], open: None }
     }
 
+    #[cfg(test)]
     pub fn open_new_group(&mut self) {
-        self.open = Some(vec![]);
+        self.open_block(0, "", vec![]);
+    }
+
+    pub fn open_block(&mut self, line: usize, tag: &str, options: Vec<CodeBlockOption>) {
+        self.open = Some(Block { lines: vec![], line, tag: tag.to_string(), options });
     }
 
     pub fn close_group(&mut self) {
@@ -27,31 +59,68 @@ impl CodeContainer {
     pub fn push(&mut self, line: String) {
         self.open = match self.open.clone() {
             Some(mut block) => {
-                block.push(line);
+                block.lines.push(line);
                 Some(block)
             },
             None => None,
         };
     }
 
+    #[cfg(test)]
     pub fn is_open(&self) -> bool {
         self.open.is_some()
     }
 
     pub fn lines(&self) -> String {
         self.blocks.iter()
-            .map(|x| x.join("\n"))
+            .map(|x| x.lines.join("\n"))
             .collect::<Vec<String>>()
             .join("\n")
     }
 
     pub fn open_lines(&self) -> Option<String> {
         if let Some(block) = self.open.clone() {
-            return Some(block.join("\n"))
+            return Some(block.lines.join("\n"))
+        }
+
+        None
+    }
+
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    /// Inserts a line before the code of each block.
+    pub fn prepend(&mut self, line: impl Fn(&Block) -> String) {
+        for block in &mut self.blocks {
+            let line = line(block);
+            block.lines.insert(0, line);
+        }
+    }
+
+    /// Line number in the source file of a line in the combined script
+    /// (both 1-based).
+    pub fn source_line(&self, line: usize) -> Option<usize> {
+        let mut offset = line.checked_sub(1)?;
+
+        for block in &self.blocks {
+            if offset < block.lines.len() {
+                return Some(block.line + 1 + offset)
+            }
+            offset -= block.lines.len();
         }
 
         None
     }
+
+    /// Splits the container into one container per block, so they can be
+    /// evaluated one by one.
+    pub fn split(self) -> Vec<CodeContainer> {
+        self.blocks
+            .into_iter()
+            .map(|block| CodeContainer { blocks: vec![block], open: None })
+            .collect()
+    }
 }
 
 #[cfg(test)]
@@ -159,4 +228,50 @@ mod tests {
         assert!(code.open_lines().is_none());
     }
 
+    #[test]
+    fn test_source_line() {
+        let mut code = CodeContainer::new();
+
+        code.open_block(3, "", vec![]);
+        code.push("line 1".into());
+        code.close_group();
+
+        code.open_block(8, "", vec![]);
+        code.push("line 2".into());
+        code.push("line 3".into());
+        code.close_group();
+
+        let test_cases: Vec<(usize, Option<usize>)> = vec![
+            (0, None),
+            (1, Some(4)),
+            (2, Some(9)),
+            (3, Some(10)),
+            (4, None),
+        ];
+
+        for case in test_cases {
+            assert_eq!(code.source_line(case.0), case.1);
+        }
+    }
+
+    #[test]
+    fn test_split() {
+        let mut code = CodeContainer::new();
+
+        code.open_block(3, "", vec![]);
+        code.push("line 1".into());
+        code.close_group();
+
+        code.open_block(8, "", vec![CodeBlockOption { key: "timeout".into(), value: "3s".into() }]);
+        code.push("line 2".into());
+        code.push("line 3".into());
+        code.close_group();
+
+        let parts = code.split();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].lines(), "line 1");
+        assert_eq!(parts[0].blocks()[0].line, 3);
+        assert_eq!(parts[1].lines(), "line 2\nline 3");
+        assert_eq!(parts[1].blocks()[0].option("timeout"), Some("3s".into()));
+    }
 }
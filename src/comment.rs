@@ -0,0 +1,89 @@
+/// Comment syntax of a language, for lines written into generated files.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Comment {
+    pub start: String,
+    /// Empty for line comments.
+    pub end: String,
+}
+
+impl Comment {
+    pub fn new(start: &str, end: &str) -> Comment {
+        Comment { start: start.to_string(), end: end.to_string() }
+    }
+
+    /// Line comments, for example `#` or `//`.
+    pub fn line_prefix(start: &str) -> Comment {
+        Comment::new(start, "")
+    }
+
+    /// Comment syntax of a code block tag, None if the language has no
+    /// comments (for example JSON) or it's not known.
+    pub fn for_tag(tag: &str) -> Option<Comment> {
+        let comment = match tag.to_lowercase().as_str() {
+            "awk" | "bash" | "conf" | "dash" | "dockerfile" | "elixir" | "ex" | "exs" | "fish" | "hcl" | "jl"
+            | "julia" | "ksh" | "make" | "makefile" | "nix" | "perl" | "pl" | "powershell" | "ps1" | "pwsh"
+            | "py" | "python" | "r" | "rb" | "ruby" | "sh" | "shell" | "tcl" | "tclsh" | "terraform" | "tf"
+            | "toml" | "yaml" | "yml" | "zsh" => ("#", ""),
+            "c" | "c++" | "cc" | "cpp" | "cs" | "csharp" | "dart" | "deno" | "go" | "golang" | "groovy" | "java"
+            | "cjs" | "cts" | "javascript" | "js" | "jsonc" | "jsx" | "kotlin" | "mjs" | "mts" | "node" | "proto"
+            | "rs" | "rust" | "scala" | "swift" | "ts" | "tsx" | "typescript" => ("//", ""),
+            "elm" | "haskell" | "hs" | "lua" | "sql" | "sqlite" | "sqlite3" => ("--", ""),
+            "erlang" | "latex" | "matlab" | "tex" => ("%", ""),
+            "asm" | "clojure" | "ini" | "lisp" | "scheme" => (";", ""),
+            "html" | "markdown" | "md" | "svg" | "xml" => ("<!--", "-->"),
+            _ => return None,
+        };
+
+        Some(Comment::new(comment.0, comment.1))
+    }
+
+    /// The text as a comment line.
+    pub fn line(&self, text: &str) -> String {
+        match self.end.as_str() {
+            "" => format!("{} {}", self.start, text),
+            end => format!("{} {} {}", self.start, text, end),
+        }
+    }
+
+    /// Text of a comment line, None if the line is not a comment.
+    pub fn text<'a>(&self, line: &'a str) -> Option<&'a str> {
+        let text = line.trim().strip_prefix(self.start.as_str())?;
+        let text = text.strip_suffix(self.end.as_str())?;
+
+        Some(text.trim())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_tag() {
+        let test_cases: Vec<(&str, Option<(&str, &str)>)> = vec![
+            ("bash", Some(("#", ""))),
+            ("YAML", Some(("#", ""))),
+            ("rust", Some(("//", ""))),
+            ("sql", Some(("--", ""))),
+            ("html", Some(("<!--", "-->"))),
+            ("json", None),
+        ];
+
+        for case in test_cases {
+            assert_eq!(Comment::for_tag(case.0), case.1.map(|(start, end)| Comment::new(start, end)));
+        }
+    }
+
+    #[test]
+    fn test_line_and_text() {
+        let hash = Comment::line_prefix("#");
+        let markup = Comment::new("<!--", "-->");
+
+        assert_eq!(hash.line("eval-md:doc.md:3"), "# eval-md:doc.md:3");
+        assert_eq!(markup.line("eval-md:doc.md:3"), "<!-- eval-md:doc.md:3 -->");
+
+        assert_eq!(hash.text("  # eval-md:doc.md:3 "), Some("eval-md:doc.md:3"));
+        assert_eq!(markup.text("<!-- note -->"), Some("note"));
+        assert_eq!(Comment::line_prefix("//").text("let a = 1; // note"), None);
+    }
+}
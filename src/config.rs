@@ -0,0 +1,130 @@
+use std::{collections::BTreeMap, env, fs, path::{Path, PathBuf}};
+
+use serde::Deserialize;
+
+use crate::{error::Error, executor::Custom};
+
+const PROJECT_FILE: &str = ".eval-md.toml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub languages: BTreeMap<String, Custom>,
+}
+
+impl Config {
+    /// Loads the user configuration (`~/.config/eval-md/config.toml`), then
+    /// the first `.eval-md.toml` from the document directory or its parents.
+    /// Project level definitions override the user level ones.
+    pub fn load(document_dir: &Path) -> Result<Config, Error> {
+        let mut config = Config::default();
+
+        let files = [user_config_file(), project_config_file(document_dir)];
+        for file in files.into_iter().flatten() {
+            config.merge(Config::read(&file)?);
+        }
+
+        Ok(config)
+    }
+
+    pub fn read(path: &Path) -> Result<Config, Error> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| Error::io(format!("unable to read {}", path.display()), err))?;
+
+        Config::parse(&content).map_err(|err| Error::Parse(format!("{}: {}", path.display(), err)))
+    }
+
+    pub fn parse(content: &str) -> Result<Config, Error> {
+        let mut config: Config = toml::from_str(content)
+            .map_err(|err| Error::Parse(err.to_string().trim_end().to_string()))?;
+
+        for (name, lang) in config.languages.iter_mut() {
+            lang.name = name.clone();
+            lang.resolve_input()?;
+        }
+
+        Ok(config)
+    }
+
+    fn merge(&mut self, other: Config) {
+        self.languages.extend(other.languages);
+    }
+}
+
+fn user_config_file() -> Option<PathBuf> {
+    let dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+
+    Some(dir.join("eval-md").join("config.toml")).filter(|f| f.is_file())
+}
+
+fn project_config_file(document_dir: &Path) -> Option<PathBuf> {
+    let start = fs::canonicalize(document_dir).ok()?;
+
+    start.ancestors()
+        .map(|dir| dir.join(PROJECT_FILE))
+        .find(|f| f.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::Input;
+
+    #[test]
+    fn test_parse() {
+        let content = r#"
+[languages.fennel]
+binary = "fennel"
+aliases = ["fnl"]
+extension = ".fnl"
+
+[languages.jq]
+binary = "jq"
+args = ["-f", "{script}", "{args}"]
+input = "stdin"
+shebang = ""
+"#;
+
+        let config = Config::parse(content).unwrap();
+        let fennel = config.languages.get("fennel").unwrap();
+        assert_eq!(fennel.name, "fennel");
+        assert_eq!(fennel.args, vec!["{script}", "{args}"]);
+        assert_eq!(fennel.aliases, vec!["fnl"]);
+        assert_eq!(fennel.input, None);
+
+        let jq = config.languages.get("jq").unwrap();
+        assert_eq!(jq.args, vec!["-f", "{script}", "{args}"]);
+        assert_eq!(jq.input, Some(Input::Stdin));
+        assert_eq!(jq.shebang, Some("".into()));
+
+        let config = Config::parse("[languages.awk]\nbinary = \"awk\"\nargs = [\"-f\", \"-\"]\n").unwrap();
+        assert_eq!(config.languages.get("awk").unwrap().input, Some(Input::Stdin));
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let test_cases: Vec<&str> = vec![
+            "[languages.fennel]\n",
+            "[languages.fennel]\nbinary = \"fennel\"\nunknown = 1\n",
+            "[languages.fennel]\nbinary = \"fennel\"\ninput = \"pipe\"\n",
+            "[languages.fennel]\nbinary = \"fennel\"\nargs = [\"-\"]\ninput = \"file\"\n",
+        ];
+
+        for case in test_cases {
+            assert!(Config::parse(case).is_err());
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut config = Config::parse("[languages.a]\nbinary = \"a\"\n[languages.b]\nbinary = \"b\"\n").unwrap();
+        config.merge(Config::parse("[languages.b]\nbinary = \"b2\"\n").unwrap());
+
+        assert_eq!(config.languages.get("a").unwrap().binary, "a");
+        assert_eq!(config.languages.get("b").unwrap().binary, "b2");
+    }
+}
@@ -0,0 +1,142 @@
+/// Lines of context around the changes.
+const CONTEXT: usize = 3;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Edit<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Unified diff of two texts, line by line. Empty if they are the same.
+pub fn unified(expected: &str, actual: &str) -> String {
+    let old: Vec<&str> = expected.lines().collect();
+    let new: Vec<&str> = actual.lines().collect();
+    let edits = edits(&old, &new);
+
+    let changes: Vec<usize> = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, edit)| !matches!(edit, Edit::Same(_)))
+        .map(|(index, _)| index)
+        .collect();
+    if changes.is_empty() {
+        return String::new()
+    }
+
+    // Line numbers before each edit.
+    let mut positions = Vec::with_capacity(edits.len() + 1);
+    let (mut old_line, mut new_line) = (0, 0);
+    for edit in &edits {
+        positions.push((old_line, new_line));
+        match edit {
+            Edit::Same(_) => { old_line += 1; new_line += 1 },
+            Edit::Removed(_) => old_line += 1,
+            Edit::Added(_) => new_line += 1,
+        }
+    }
+    positions.push((old_line, new_line));
+
+    let mut diff = String::from("--- expected\n+++ actual\n");
+    let mut index = 0;
+    while index < changes.len() {
+        // Changes close to each other share a hunk.
+        let mut last = index;
+        while last + 1 < changes.len() && changes[last + 1] - changes[last] <= 2 * CONTEXT + 1 {
+            last += 1;
+        }
+
+        let start = changes[index].saturating_sub(CONTEXT);
+        let end = (changes[last] + CONTEXT + 1).min(edits.len());
+        let (old_start, new_start) = positions[start];
+        let (old_end, new_end) = positions[end];
+
+        diff.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(old_start, old_end - old_start),
+            hunk_range(new_start, new_end - new_start),
+        ));
+        for edit in &edits[start..end] {
+            let (prefix, line) = match edit {
+                Edit::Same(line) => (' ', line),
+                Edit::Removed(line) => ('-', line),
+                Edit::Added(line) => ('+', line),
+            };
+            diff.push(prefix);
+            diff.push_str(line);
+            diff.push('\n');
+        }
+
+        index = last + 1;
+    }
+
+    diff
+}
+
+/// Range of a hunk, an empty range starts at the line before it.
+fn hunk_range(start: usize, count: usize) -> String {
+    match count {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, count),
+    }
+}
+
+/// Shortest edit script based on the longest common subsequence.
+fn edits<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Edit<'a>> {
+    // lengths[i][j] is the length of the LCS of old[i..] and new[j..].
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut edits = Vec::with_capacity(old.len() + new.len());
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            edits.push(Edit::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            edits.push(Edit::Removed(old[i]));
+            i += 1;
+        } else {
+            edits.push(Edit::Added(new[j]));
+            j += 1;
+        }
+    }
+    edits.extend(old[i..].iter().map(|line| Edit::Removed(line)));
+    edits.extend(new[j..].iter().map(|line| Edit::Added(line)));
+
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified() {
+        let test_cases: Vec<(&str, &str, &str)> = vec![
+            ("a\nb\n", "a\nb\n", ""),
+            ("a\nb\nc\n", "a\nx\nc\n", "--- expected\n+++ actual\n@@ -1,3 +1,3 @@\n a\n-b\n+x\n c\n"),
+            ("", "a\n", "--- expected\n+++ actual\n@@ -0,0 +1 @@\n+a\n"),
+            ("a\n", "", "--- expected\n+++ actual\n@@ -1 +0,0 @@\n-a\n"),
+            (
+                "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n",
+                "x\n2\n3\n4\n5\n6\n7\n8\n9\ny\n",
+                "--- expected\n+++ actual\n@@ -1,4 +1,4 @@\n-1\n+x\n 2\n 3\n 4\n@@ -7,4 +7,4 @@\n 7\n 8\n 9\n-10\n+y\n",
+            ),
+        ];
+
+        for case in test_cases {
+            assert_eq!(unified(case.0, case.1), case.2);
+        }
+    }
+}
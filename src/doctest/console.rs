@@ -0,0 +1,237 @@
+use std::io;
+
+use crate::code_block_options::{find_option, CodeBlockOption, CB_OPTION_EXIT, CB_OPTION_PROMPT};
+use crate::document::{Fence, Update};
+use crate::error::Error;
+use crate::executor::{Outcome, Registry};
+use crate::runner::{describe_script, Runner};
+
+use super::{compare, exit_failure, normalize, script, CaseResult};
+
+/// Tags of console transcripts.
+const CONSOLE_TAGS: [&str; 2] = ["console", "shell-session"];
+
+/// Prompts of the commands, the "prompt" option of the block and --prompt
+/// can add more.
+const DEFAULT_PROMPTS: [&str; 2] = ["$ ", "❯ "];
+
+/// Shell of the commands.
+const SHELL: &str = "bash";
+
+/// A command of a console transcript with its expected output.
+#[derive(Debug, Default, Eq, PartialEq)]
+struct Command<'a> {
+    /// Line of the prompt in the document (1-based).
+    line: usize,
+    /// Lines of the command as they are in the document.
+    prompt: Vec<&'a str>,
+    /// Lines of the command without the prompt.
+    code: Vec<&'a str>,
+    expected: Vec<&'a str>,
+}
+
+pub fn is_console(fence: &Fence) -> bool {
+    CONSOLE_TAGS.contains(&fence.tag.as_str())
+}
+
+/// Splits the transcript into the lines before the first prompt and the
+/// commands. A command goes on in the next line if it ends with a backslash.
+fn parse<'a>(lines: Vec<&'a str>, first_line: usize, prompts: &[String]) -> (Vec<&'a str>, Vec<Command<'a>>) {
+    let mut preamble = vec![];
+    let mut commands: Vec<Command> = vec![];
+    let mut continued = false;
+
+    for (index, line) in lines.into_iter().enumerate() {
+        if let Some(command) = commands.last_mut().filter(|_| continued) {
+            command.prompt.push(line);
+            command.code.push(line);
+            continued = line.trim_end().ends_with('\\');
+            continue
+        }
+
+        let code = prompts.iter().find_map(|prompt| line.strip_prefix(prompt.as_str()));
+        match (code, commands.last_mut()) {
+            (Some(code), _) => {
+                commands.push(Command { line: first_line + index, prompt: vec![line], code: vec![code], expected: vec![] });
+                continued = code.trim_end().ends_with('\\');
+            },
+            (None, Some(command)) => command.expected.push(line),
+            (None, None) => preamble.push(line),
+        }
+    }
+
+    (preamble, commands)
+}
+
+/// Runs the commands of a console block one by one in the same shell, and
+/// compares their output with the lines after them. Every command has to
+/// succeed, except the last one with the "exit" option, which checks its
+/// status instead.
+pub fn check(
+    runner: &Runner,
+    registry: &Registry,
+    file: &str,
+    source: &str,
+    fence: &Fence,
+    prompts: &[String],
+) -> CaseResult {
+    let mut result = CaseResult::new(fence);
+
+    let options = CodeBlockOption::parse_options(&fence.info);
+    let mut all_prompts: Vec<String> = DEFAULT_PROMPTS.iter().map(|prompt| prompt.to_string()).collect();
+    all_prompts.extend(
+        options
+            .iter()
+            .filter(|option| option.key == CB_OPTION_PROMPT)
+            .map(|option| option.value.clone()),
+    );
+    all_prompts.extend(prompts.iter().cloned());
+    let lines: Vec<&str> = fence.lines(source).collect();
+    let (preamble, commands) = parse(lines.clone(), fence.line + 1, &all_prompts);
+
+    // Errors are part of the output, like on a terminal.
+    let started = registry.pick(SHELL, SHELL)
+        .and_then(|shell| shell.session(vec![], &runner.context(&script(fence, fence.line, &lines))?))
+        .and_then(|mut session| session.eval("exec 2>&1", None, &mut io::sink()).map(|_| session));
+    let mut session = match started {
+        Ok(session) => session,
+        Err(err) => {
+            result.failures.push(err.to_string());
+            return result
+        },
+    };
+
+    // The transcript with the actual output of the commands that don't
+    // match, for --bless.
+    let mut body: Vec<&str> = preamble;
+    let mut outputs = vec![];
+    let mut diffs = String::new();
+    let mut running = true;
+    for (index, command) in commands.iter().enumerate() {
+        if !running {
+            outputs.push(None);
+            continue
+        }
+        let location = format!("{}:{}\n{}", file, command.line, command.prompt.join("\n"));
+
+        let script = script(fence, command.line, &command.code);
+        let mut output = vec![];
+        let outcome = runner.limit(&script)
+            .and_then(|(limit, reported)| Ok((session.eval(&script.lines(), limit, &mut output)?, reported)));
+
+        let status = match outcome {
+            Ok((Outcome::Done(status), _)) => Some(status),
+            Ok((Outcome::Exited(status), _)) => {
+                running = false;
+                result.failures.push(format!("{}\nthe shell exited during the command", location));
+                status.code()
+            },
+            Ok((Outcome::TimedOut, reported)) => {
+                running = false;
+                let err = Error::Timeout { target: describe_script(file, &script), limit: reported };
+                result.failures.push(format!("{}\n{}", location, err));
+                outputs.push(None);
+                continue
+            },
+            Err(err) => {
+                running = false;
+                result.failures.push(format!("{}\n{}", location, err));
+                outputs.push(None);
+                continue
+            },
+        };
+        result.exit_code = status;
+
+        let expected_exit = find_option(&options, CB_OPTION_EXIT).filter(|_| index + 1 == commands.len());
+        if let Some(failure) = exit_failure(expected_exit.as_deref(), status).filter(|_| running) {
+            result.failures.push(format!("{}\n{}", location, failure));
+        }
+
+        let output = String::from_utf8_lossy(&output).to_string();
+        let diff = compare(&command.expected.join("\n"), &output);
+        if let Some(diff) = &diff {
+            diffs.push_str(&format!("{}\n{}", location, diff));
+        }
+        outputs.push(diff.map(|_| output));
+    }
+    let _ = session.finish();
+
+    for (command, output) in commands.iter().zip(&outputs) {
+        body.extend(&command.prompt);
+        match output {
+            Some(output) => {
+                // Blank lines after the output separate the commands.
+                let blank = command.expected.iter().rev().take_while(|line| line.trim().is_empty()).count();
+                body.extend(normalize(output));
+                body.extend(std::iter::repeat_n("", blank));
+            },
+            None => body.extend(&command.expected),
+        }
+    }
+
+    let mut transcript = body.join("\n");
+    if !transcript.is_empty() {
+        transcript.push('\n');
+    }
+    result.diff = Some(diffs).filter(|diffs| !diffs.is_empty());
+    result.output = Some(transcript.clone());
+    result.update = Some(Update::Body(transcript));
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::document;
+    use crate::RunOptions;
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let prompts: Vec<String> = vec!["$ ".into(), "❯ ".into(), "PS> ".into()];
+        let lines = vec![
+            "Intro",
+            "$ echo a",
+            "a",
+            "",
+            "❯ printf '%s\\n' \\",
+            "    b",
+            "b",
+            "PS> Get-Date",
+            "...",
+        ];
+
+        let (preamble, commands) = parse(lines, 2, &prompts);
+        assert_eq!(preamble, vec!["Intro"]);
+        assert_eq!(commands, vec![
+            Command { line: 3, prompt: vec!["$ echo a"], code: vec!["echo a"], expected: vec!["a", ""] },
+            Command {
+                line: 6,
+                prompt: vec!["❯ printf '%s\\n' \\", "    b"],
+                code: vec!["printf '%s\\n' \\", "    b"],
+                expected: vec!["b"],
+            },
+            Command { line: 9, prompt: vec!["PS> Get-Date"], code: vec!["Get-Date"], expected: vec!["..."] },
+        ]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_check() {
+        let source = concat!(
+            "```console\n$ cd / && export NAME=world\n$ echo \"$NAME\"; pwd\nworld\n/\n$ false\n$ echo next\nnext\n```\n",
+            "```console #exit=1\n$ greet() { echo \"hi $1\"; }\n$ greet a; false\nhi a\n```\n",
+        );
+        let runner = Runner::new("doc.md", source, &RunOptions::default(), false).unwrap();
+        let fences = document::fences(source);
+
+        let result = check(&runner, &Registry::default(), "doc.md", source, &fences[0], &[]);
+        assert_eq!(result.diff, None);
+        assert_eq!(result.failures, vec!["doc.md:6\n$ false\nexited with status 1"]);
+
+        let result = check(&runner, &Registry::default(), "doc.md", source, &fences[1], &[]);
+        assert!(result.passed(), "{:?}", result);
+        assert_eq!(result.exit_code, Some(1));
+    }
+}
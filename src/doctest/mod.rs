@@ -0,0 +1,493 @@
+mod console;
+mod pycon;
+pub mod report;
+
+use std::{fs, io::{self, Write}, process::ExitCode, time::Instant};
+
+use regex::RegexBuilder;
+
+use crate::code_block_options::{
+    CodeBlockOption, CB_OPTION_EXIT, CB_OPTION_EXPECT, CB_OPTION_STDERR_CONTAINS, CB_OPTION_STDOUT_MATCHES, CB_OPTION_TIMEOUT,
+    find_option,
+};
+use crate::code_container::CodeContainer;
+use crate::diff;
+use crate::document::{self, Fence, Update};
+use crate::error::Error;
+use crate::executor::Registry;
+use crate::runner::{Capture, Evaluation, Runner};
+use crate::RunOptions;
+
+/// Line of an expected output that matches any number of lines.
+const WILDCARD: &str = "...";
+
+/// Value of the "exit" option that accepts any failure.
+const NONZERO: &str = "nonzero";
+
+/// Options that check the result of a block, even without an expected
+/// output block.
+const ASSERTIONS: [&str; 3] = [CB_OPTION_EXIT, CB_OPTION_STDERR_CONTAINS, CB_OPTION_STDOUT_MATCHES];
+
+/// A test case of the document.
+#[derive(Debug)]
+pub enum Case {
+    /// A code block with its expected output block, or with options that
+    /// check its result.
+    Block { code: Fence, expected: Option<Fence> },
+    /// Commands after a prompt, each followed by its expected output.
+    Console(Fence),
+    /// Python interactive session with the output of the statements.
+    Pycon(Fence),
+}
+
+impl Case {
+    pub fn fence(&self) -> &Fence {
+        match self {
+            Case::Block { code, .. } => code,
+            Case::Console(fence) | Case::Pycon(fence) => fence,
+        }
+    }
+}
+
+/// Result of a test case.
+#[derive(Debug)]
+pub struct CaseResult {
+    pub line: usize,
+    pub tag: String,
+    /// Output of the block, with stderr too if it's not captured on its own.
+    pub output: Option<String>,
+    pub stderr: Option<String>,
+    /// Exit status of the block, or of the last command in a console block.
+    pub exit_code: Option<i32>,
+    /// Diff of the expected and the actual output, if they don't match.
+    pub diff: Option<String>,
+    /// Other reasons of the failure, for example the exit status.
+    pub failures: Vec<String>,
+    /// Change of the document that makes the expected output the actual one.
+    pub update: Option<Update>,
+}
+
+impl CaseResult {
+    fn new(fence: &Fence) -> CaseResult {
+        CaseResult {
+            line: fence.line,
+            tag: fence.tag.clone(),
+            output: None,
+            stderr: None,
+            exit_code: None,
+            diff: None,
+            failures: vec![],
+            update: None,
+        }
+    }
+
+    pub fn passed(&self) -> bool {
+        self.diff.is_none() && self.failures.is_empty()
+    }
+
+    /// True if only the output is different, so blessing fixes it.
+    fn blessable(&self) -> bool {
+        self.diff.is_some() && self.failures.is_empty()
+    }
+}
+
+/// True if the block holds the expected output of the previous one: it has
+/// the output tag or the "expect" option.
+pub fn is_expectation(fence: &Fence, output_tag: &str) -> bool {
+    fence.tag == output_tag
+        || find_option(&CodeBlockOption::parse_options(&fence.info), CB_OPTION_EXPECT).is_some()
+}
+
+/// Test cases of the document: code blocks with an expected output block right
+/// after them (only blank lines can be between the two), code blocks with
+/// assertion options, console blocks and Python sessions.
+pub fn cases(source: &str, output_tag: &str) -> Vec<Case> {
+    let fences = document::fences(source);
+
+    fences
+        .iter()
+        .enumerate()
+        .filter(|(_, code)| code.closed && !code.tag.is_empty() && !is_expectation(code, output_tag))
+        .filter_map(|(index, code)| {
+            if console::is_console(code) {
+                return Some(Case::Console(code.clone()))
+            }
+            if pycon::is_pycon(code) {
+                return Some(Case::Pycon(code.clone()))
+            }
+
+            let expected = fences.get(index + 1).filter(|next| {
+                next.closed
+                    && is_expectation(next, output_tag)
+                    && source[code.end..next.start].trim().is_empty()
+            });
+            let options = CodeBlockOption::parse_options(&code.info);
+            let asserted = ASSERTIONS.iter().any(|key| find_option(&options, key).is_some());
+
+            (expected.is_some() || asserted).then(|| Case::Block { code: code.clone(), expected: expected.cloned() })
+        })
+        .collect()
+}
+
+/// True if the case can't be evaluated, its tag is not a known language, for
+/// example a `json` block with an expected output.
+fn is_skipped(registry: &Registry, case: &Case) -> bool {
+    match case {
+        Case::Block { code, .. } => matches!(registry.pick(&code.tag, &code.tag), Err(Error::UnknownLanguage(_))),
+        Case::Console(_) | Case::Pycon(_) => false,
+    }
+}
+
+/// Compares the output with the expected one. Trailing whitespace, line
+/// endings and blank lines around the text are ignored, and a "..." line
+/// matches any number of lines.
+pub fn matches(expected: &str, actual: &str) -> bool {
+    let expected = normalize(expected);
+    let actual = normalize(actual);
+
+    // matched[i][j] is true if expected[i..] matches actual[j..].
+    let mut matched = vec![vec![false; actual.len() + 1]; expected.len() + 1];
+    matched[expected.len()][actual.len()] = true;
+    for i in (0..expected.len()).rev() {
+        for j in (0..=actual.len()).rev() {
+            matched[i][j] = if expected[i] == WILDCARD {
+                matched[i + 1][j] || (j < actual.len() && matched[i][j + 1])
+            } else {
+                j < actual.len() && expected[i] == actual[j] && matched[i + 1][j + 1]
+            };
+        }
+    }
+
+    matched[0][0]
+}
+
+/// Lines without trailing whitespace and blank lines around them.
+fn normalize(text: &str) -> Vec<&str> {
+    let lines: Vec<&str> = text.lines().map(str::trim_end).collect();
+    let start = lines.iter().position(|line| !line.is_empty()).unwrap_or(lines.len());
+    let end = lines.iter().rposition(|line| !line.is_empty()).map_or(start, |end| end + 1);
+
+    lines[start..end].to_vec()
+}
+
+/// Diff of the normalized outputs, if they don't match.
+fn compare(expected: &str, actual: &str) -> Option<String> {
+    if matches(expected, actual) {
+        return None
+    }
+
+    Some(diff::unified(&normalize(expected).join("\n"), &normalize(actual).join("\n")))
+}
+
+/// Script of a single block or command, with the options of the block.
+fn script(fence: &Fence, line: usize, lines: &[&str]) -> CodeContainer {
+    let mut script = CodeContainer::new();
+    script.open_block(line, &fence.tag, CodeBlockOption::parse_options(&fence.info));
+    for line in lines {
+        script.push(line.to_string());
+    }
+    script.close_group();
+
+    script
+}
+
+/// Evaluates the test cases of the document and reports the results. With
+/// `bless`, the expected output of the failing blocks is replaced with the
+/// actual one. Console commands are recognized by the default prompts and
+/// the extra `prompts`. If a report goes to stdout, the results are printed
+/// on stderr.
+pub fn run(
+    file: &str,
+    bless: bool,
+    prompts: &[String],
+    reports: &[report::Target],
+    options: &RunOptions,
+    output_tag: &str,
+    registry: &Registry,
+) -> Result<ExitCode, Error> {
+    let source = fs::read_to_string(file)
+        .map_err(|err| Error::io(format!("unable to read {}", file), err))?;
+
+    let mut out: Box<dyn Write> = match reports.iter().any(|target| target.path.is_none()) {
+        true => Box::new(io::stderr()),
+        false => Box::new(io::stdout()),
+    };
+    let write_error = |err| Error::io("unable to write the results", err);
+
+    let (skipped, cases): (Vec<Case>, Vec<Case>) = cases(&source, output_tag)
+        .into_iter()
+        .partition(|case| is_skipped(registry, case));
+    let has_timeout = cases.iter().any(|case| {
+        find_option(&CodeBlockOption::parse_options(&case.fence().info), CB_OPTION_TIMEOUT).is_some()
+    });
+    let runner = Runner::new(file, &source, options, has_timeout)?;
+    let headings = document::headings(&source);
+    let started = Instant::now();
+
+    writeln!(out, "running {} tests", cases.len()).map_err(write_error)?;
+    for case in &skipped {
+        let fence = case.fence();
+        writeln!(out, "test {}:{} ({}) ... skipped", file, fence.line, fence.tag).map_err(write_error)?;
+    }
+    let mut results = vec![];
+    let mut reported = vec![];
+    for case in cases {
+        let case_started = Instant::now();
+        let result = match &case {
+            Case::Block { code, expected } => check(&runner, registry, &source, code, expected.as_ref()),
+            Case::Console(fence) => console::check(&runner, registry, file, &source, fence, prompts),
+            Case::Pycon(fence) => pycon::check(&runner, registry, file, &source, fence),
+        };
+        let (status, verdict) = match (result.passed(), bless && result.blessable()) {
+            (true, _) => ("passed", "ok"),
+            (false, true) => ("blessed", "blessed"),
+            (false, false) => ("failed", "FAILED"),
+        };
+        writeln!(out, "test {}:{} ({}) ... {}", file, result.line, result.tag, verdict).map_err(write_error)?;
+
+        reported.push(report::CaseReport {
+            name: format!("{}:{} ({})", file, result.line, result.tag),
+            heading: headings.iter().rev().find(|(line, _)| *line < result.line).map(|(_, title)| title.clone()),
+            file: file.to_string(),
+            line: result.line,
+            tag: result.tag.clone(),
+            status,
+            duration: case_started.elapsed().as_secs_f64(),
+            exit_code: result.exit_code,
+            stdout: result.output.clone(),
+            stderr: result.stderr.clone(),
+            failures: result.failures.clone(),
+            diff: result.diff.clone(),
+        });
+        results.push(result);
+    }
+
+    let failed: Vec<&CaseResult> = results
+        .iter()
+        .filter(|result| !(result.passed() || bless && result.blessable()))
+        .collect();
+    if !failed.is_empty() {
+        writeln!(out, "\nfailures:").map_err(write_error)?;
+        for result in &failed {
+            writeln!(out, "\n---- {}:{} ({}) ----", file, result.line, result.tag).map_err(write_error)?;
+            for failure in &result.failures {
+                writeln!(out, "{}", failure).map_err(write_error)?;
+            }
+            if let Some(diff) = &result.diff {
+                write!(out, "{}", diff).map_err(write_error)?;
+            }
+        }
+    }
+
+    if bless {
+        let updates: Vec<(usize, Update)> = results
+            .iter()
+            .filter(|result| result.diff.is_some())
+            .filter_map(|result| Some((result.line, result.update.clone()?)))
+            .collect();
+        let is_output = |fence: &Fence| is_expectation(fence, output_tag);
+        let updated = document::update_blocks(&source, &updates, &is_output, output_tag);
+        if updated != source {
+            fs::write(file, updated)
+                .map_err(|err| Error::io(format!("unable to write {}", file), err))?;
+        }
+    }
+
+    let passed = results.len() - failed.len();
+    writeln!(
+        out,
+        "\ntest result: {}. {} passed; {} failed; {} skipped",
+        if failed.is_empty() { "ok" } else { "FAILED" },
+        passed,
+        failed.len(),
+        skipped.len(),
+    ).map_err(write_error)?;
+
+    let suite = report::Suite {
+        file: file.to_string(),
+        tests: results.len(),
+        passed,
+        failed: failed.len(),
+        duration: started.elapsed().as_secs_f64(),
+        cases: reported,
+    };
+    for target in reports {
+        report::write(target, &suite)?;
+    }
+
+    Ok(if failed.is_empty() { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
+
+/// Evaluates a code block and checks its result.
+fn check(runner: &Runner, registry: &Registry, source: &str, code: &Fence, expected: Option<&Fence>) -> CaseResult {
+    let mut result = CaseResult::new(code);
+
+    // The output is compared with stdout only, if stderr is checked on its own.
+    let options = CodeBlockOption::parse_options(&code.info);
+    let capture = match find_option(&options, CB_OPTION_STDERR_CONTAINS).or(find_option(&options, CB_OPTION_STDOUT_MATCHES)) {
+        Some(_) => Capture::Separate,
+        None => Capture::Combined,
+    };
+
+    let lines: Vec<&str> = code.lines(source).collect();
+    let evaluated = registry
+        .pick(&code.tag, &code.tag)
+        .and_then(|lang| runner.eval(lang.as_ref(), script(code, code.line, &lines), vec![], capture));
+    let evaluation = match evaluated {
+        Ok(evaluation) => evaluation,
+        Err(err) => {
+            result.failures.push(err.to_string());
+            return result
+        },
+    };
+
+    result.failures = assertion_failures(&options, &evaluation);
+    result.exit_code = evaluation.status.code();
+    result.stderr = evaluation.stderr.clone();
+
+    let output = evaluation.output.unwrap_or_default();
+    if let Some(expected) = expected {
+        result.diff = compare(&source[expected.body.clone()], &output);
+        result.update = Some(Update::Output(output.clone()));
+    }
+    result.output = Some(output);
+
+    result
+}
+
+/// Checks the exit status with the "exit" option, without it the status has
+/// to be 0. The status is None if the process was terminated by a signal.
+fn exit_failure(expected: Option<&str>, status: Option<i32>) -> Option<String> {
+    let described = match status {
+        Some(code) => format!("exited with status {}", code),
+        None => "terminated by a signal".to_string(),
+    };
+
+    match expected {
+        None if status != Some(0) => Some(described),
+        None => None,
+        Some(NONZERO) if status == Some(0) => Some(format!("{}, expected a non-zero status", described)),
+        Some(NONZERO) => None,
+        Some(expected) => match expected.parse::<i32>() {
+            Ok(code) if status != Some(code) => Some(format!("{}, expected {}", described, code)),
+            Ok(_) => None,
+            Err(_) => Some(format!("invalid exit option: {}", expected)),
+        },
+    }
+}
+
+/// Checks the exit status and the output of a block with its options. Without
+/// the "exit" option the block has to succeed.
+fn assertion_failures(options: &[CodeBlockOption], evaluation: &Evaluation) -> Vec<String> {
+    let mut failures: Vec<String> = exit_failure(find_option(options, CB_OPTION_EXIT).as_deref(), evaluation.status.code())
+        .into_iter()
+        .collect();
+
+    let stdout = evaluation.output.as_deref().unwrap_or_default();
+    let stderr = evaluation.stderr.as_deref().unwrap_or_default();
+    for option in options {
+        match option.key.as_str() {
+            CB_OPTION_STDERR_CONTAINS if !stderr.contains(&option.value) => {
+                failures.push(format!("stderr doesn't contain {:?}, stderr:\n{}", option.value, stderr.trim_end()));
+            },
+            CB_OPTION_STDOUT_MATCHES => match RegexBuilder::new(&option.value).multi_line(true).build() {
+                Ok(regex) if !regex.is_match(stdout) => {
+                    failures.push(format!("stdout doesn't match {:?}, stdout:\n{}", option.value, stdout.trim_end()));
+                },
+                Ok(_) => {},
+                Err(err) => failures.push(format!("invalid regex in stdout-matches: {}", err)),
+            },
+            _ => {},
+        }
+    }
+
+    failures
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::ExitStatus;
+
+    use super::*;
+
+    #[test]
+    fn test_cases() {
+        let source = "```bash\necho a\n```\n\n```output\na\n```\n\n```bash\necho b\n```\ntext\n```output\nb\n```\n\n```python\nprint(1)\n```\n```text #expect\n1\n```\n";
+        let lines = |cases: Vec<Case>| -> Vec<(usize, Option<usize>)> {
+            cases
+                .iter()
+                .map(|case| match case {
+                    Case::Block { code, expected } => (code.line, expected.as_ref().map(|e| e.line)),
+                    Case::Console(fence) | Case::Pycon(fence) => (fence.line, None),
+                })
+                .collect()
+        };
+
+        assert_eq!(lines(cases(source, "output")), vec![(1, Some(5)), (17, Some(20))]);
+
+        let source = "```bash #exit=1\nfalse\n```\n\n```bash #group=a\ntrue\n```\n\n```console\n$ true\n```\n\n```pycon\n>>> 1\n1\n```\n";
+        assert_eq!(lines(cases(source, "output")), vec![(1, None), (9, None), (13, None)]);
+    }
+
+    #[test]
+    fn test_is_skipped() {
+        let source = "```json\n{}\n```\n```output\n{}\n```\n\n```bash\necho a\n```\n```output\na\n```\n\n```console\n$ true\n```\n";
+        let registry = Registry::default();
+        let skipped: Vec<(usize, bool)> = cases(source, "output")
+            .iter()
+            .map(|case| (case.fence().line, is_skipped(&registry, case)))
+            .collect();
+
+        assert_eq!(skipped, vec![(1, true), (8, false), (15, false)]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_assertion_failures() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let evaluation = |code: i32, stdout: &str, stderr: &str| Evaluation {
+            status: ExitStatus::from_raw(code << 8),
+            output: Some(stdout.into()),
+            stderr: Some(stderr.into()),
+        };
+        let test_cases: Vec<(&str, Evaluation, usize)> = vec![
+            ("```bash", evaluation(0, "", ""), 0),
+            ("```bash", evaluation(1, "", ""), 1),
+            ("```bash #exit=1", evaluation(1, "", ""), 0),
+            ("```bash #exit=1", evaluation(2, "", ""), 1),
+            ("```bash #exit=nonzero", evaluation(2, "", ""), 0),
+            ("```bash #exit=nonzero", evaluation(0, "", ""), 1),
+            ("```bash #exit=maybe", evaluation(0, "", ""), 1),
+            ("```bash #exit=1 stderr-contains=\"not found\"", evaluation(1, "", "repo not found\n"), 0),
+            ("```bash #stderr-contains=missing", evaluation(0, "", "repo not found\n"), 1),
+            ("```bash #stdout-matches=^total:\\s\\d+$", evaluation(0, "rows\ntotal: 42\n", ""), 0),
+            ("```bash #stdout-matches=^total:$", evaluation(0, "total: 42\n", ""), 1),
+            ("```bash #stdout-matches=(", evaluation(0, "", ""), 1),
+        ];
+
+        for case in test_cases {
+            let failures = assertion_failures(&CodeBlockOption::parse_options(case.0), &case.1);
+            assert_eq!(failures.len(), case.2, "{}: {:?}", case.0, failures);
+        }
+    }
+
+    #[test]
+    fn test_matches() {
+        let test_cases: Vec<(&str, &str, bool)> = vec![
+            ("a\nb\n", "a\nb\n", true),
+            ("a\nb", "\n\na  \r\nb\r\n\n", true),
+            ("a\nb\n", "a\nc\n", false),
+            ("a\n...\nd\n", "a\nb\nc\nd\n", true),
+            ("a\n...\nd\n", "a\nd\n", true),
+            ("a\n...\nd\n", "a\nb\nc\n", false),
+            ("...\n", "anything\n", true),
+            ("", "", true),
+            ("", "a\n", false),
+        ];
+
+        for case in test_cases {
+            assert_eq!(matches(case.0, case.1), case.2, "{:?} ~ {:?}", case.0, case.1);
+        }
+    }
+}
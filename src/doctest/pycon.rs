@@ -0,0 +1,337 @@
+use crate::document::{Fence, Update};
+use crate::executor::Registry;
+use crate::runner::{Capture, Runner};
+
+use super::{compare, script, CaseResult};
+
+/// Tag of Python interactive sessions.
+const PYCON_TAG: &str = "pycon";
+
+const PROMPT: &str = ">>>";
+const CONTINUATION: &str = "...";
+
+/// Stands for an empty line in the expected output, like in doctest.
+const BLANKLINE: &str = "<BLANKLINE>";
+
+const TRACEBACK: &str = "Traceback (most recent call last):";
+
+/// Evaluates the examples one by one in the same namespace, like the
+/// interactive interpreter, so the value of expressions is printed. The output
+/// of each example follows a marker line with its index. The examples are
+/// compiled at their line in the document, so tracebacks point there.
+const DRIVER: &str = r#"
+import io, sys, traceback
+scope = {"__name__": "__main__", "__builtins__": __builtins__}
+stdout = sys.stdout
+for index, (line, source) in enumerate(EXAMPLES):
+    buffer = io.StringIO()
+    sys.stdout = buffer
+    try:
+        exec(compile("\n" * (line - 1) + source, FILE, "single"), scope)
+    except BaseException as e:
+        buffer.write("".join(traceback.format_exception(type(e), e, e.__traceback__.tb_next)))
+    finally:
+        sys.stdout = stdout
+    output = buffer.getvalue()
+    stdout.write("%s %d\n" % (SENTINEL, index))
+    stdout.write(output if not output or output.endswith("\n") else output + "\n")
+    stdout.flush()
+"#;
+
+/// A statement of the session with its expected output.
+#[derive(Debug, Default, Eq, PartialEq)]
+struct Example<'a> {
+    /// Line of the prompt in the document (1-based).
+    line: usize,
+    /// Lines of the statement as they are in the document.
+    prompt: Vec<&'a str>,
+    /// Lines of the statement without the prompts.
+    source: Vec<&'a str>,
+    expected: Vec<&'a str>,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+enum Item<'a> {
+    /// A line that doesn't belong to any example, kept as it is.
+    Text(&'a str),
+    Example(Example<'a>),
+}
+
+pub fn is_pycon(fence: &Fence) -> bool {
+    fence.tag == PYCON_TAG
+}
+
+fn strip_prompt<'a>(line: &'a str, prompt: &str) -> Option<&'a str> {
+    match line.strip_prefix(prompt)? {
+        "" => Some(""),
+        rest => rest.strip_prefix(' '),
+    }
+}
+
+/// Splits the session into examples. The expected output of an example ends
+/// at a blank line or at the next prompt.
+fn parse<'a>(lines: Vec<&'a str>, first_line: usize) -> Vec<Item<'a>> {
+    let mut items: Vec<Item> = vec![];
+
+    for (index, line) in lines.into_iter().enumerate() {
+        if let Some(source) = strip_prompt(line, PROMPT) {
+            items.push(Item::Example(Example {
+                line: first_line + index,
+                prompt: vec![line],
+                source: vec![source],
+                expected: vec![],
+            }));
+            continue
+        }
+
+        match items.last_mut() {
+            Some(Item::Example(example)) if example.expected.is_empty() && strip_prompt(line, CONTINUATION).is_some() => {
+                example.prompt.push(line);
+                example.source.extend(strip_prompt(line, CONTINUATION));
+            },
+            Some(Item::Example(example)) if !line.trim().is_empty() => example.expected.push(line),
+            _ => items.push(Item::Text(line)),
+        }
+    }
+
+    items
+}
+
+/// Python string literal of the text.
+fn python_string(text: &str) -> String {
+    let mut literal = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '\\' => literal.push_str("\\\\"),
+            '"' => literal.push_str("\\\""),
+            '\n' => literal.push_str("\\n"),
+            '\r' => literal.push_str("\\r"),
+            '\t' => literal.push_str("\\t"),
+            c if c.is_control() => literal.push_str(&format!("\\u{:04x}", c as u32)),
+            c => literal.push(c),
+        }
+    }
+    literal.push('"');
+
+    literal
+}
+
+/// The program that evaluates the examples.
+fn program(file: &str, sentinel: &str, examples: &[&Example]) -> Vec<String> {
+    let mut lines = vec![
+        format!("FILE = {}", python_string(file)),
+        format!("SENTINEL = {}", python_string(sentinel)),
+        "EXAMPLES = [".to_string(),
+    ];
+    for example in examples {
+        let source = format!("{}\n", example.source.join("\n"));
+        lines.push(format!("    ({}, {}),", example.line, python_string(&source)));
+    }
+    lines.push("]".to_string());
+    lines.extend(DRIVER.lines().map(str::to_string));
+
+    lines
+}
+
+/// Output of each example from the output of the program.
+fn split_outputs(output: &str, sentinel: &str, count: usize) -> Vec<Option<String>> {
+    let mut outputs: Vec<Option<String>> = vec![None; count];
+    let mut current: Option<usize> = None;
+
+    for line in output.split_inclusive('\n') {
+        let index = line
+            .strip_prefix(sentinel)
+            .and_then(|rest| rest.trim().parse::<usize>().ok())
+            .filter(|index| *index < count);
+        if let Some(index) = index {
+            outputs[index] = Some(String::new());
+            current = Some(index);
+            continue
+        }
+
+        if let Some(output) = current.and_then(|index| outputs[index].as_mut()) {
+            output.push_str(line);
+        }
+    }
+
+    outputs
+}
+
+/// Expected output in the form of the actual one: blank line markers are
+/// empty lines, and only the exception is kept from a traceback.
+fn comparable(lines: &[&str]) -> String {
+    let mut result = vec![];
+    let mut in_traceback = false;
+
+    for line in lines {
+        if *line == TRACEBACK {
+            in_traceback = true;
+            result.push(*line);
+            continue
+        }
+        if in_traceback && (line.starts_with(char::is_whitespace) || *line == CONTINUATION) {
+            continue
+        }
+        in_traceback = false;
+        result.push(if *line == BLANKLINE { "" } else { line });
+    }
+
+    result.join("\n")
+}
+
+/// Evaluates the examples of a pycon block in one Python session, and compares
+/// their output with the expected lines.
+pub fn check(runner: &Runner, registry: &Registry, file: &str, source: &str, fence: &Fence) -> CaseResult {
+    let mut result = CaseResult::new(fence);
+    let items = parse(fence.lines(source).collect(), fence.line + 1);
+    let examples: Vec<&Example> = items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Example(example) => Some(example),
+            Item::Text(_) => None,
+        })
+        .collect();
+
+    let sentinel = format!("__eval_md_pycon_{}__", std::process::id());
+    let lines = program(file, &sentinel, &examples);
+    let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+
+    let evaluated = registry
+        .pick("python", "python")
+        .and_then(|python| runner.eval(python.as_ref(), script(fence, fence.line, &lines), vec![], Capture::Separate));
+    let evaluation = match evaluated {
+        Ok(evaluation) => evaluation,
+        Err(err) => {
+            result.failures.push(err.to_string());
+            return result
+        },
+    };
+    result.exit_code = evaluation.status.code();
+    result.stderr = evaluation.stderr.clone();
+    if !evaluation.status.success() {
+        let stderr = evaluation.stderr.unwrap_or_default();
+        result.failures.push(format!("python failed: {}", stderr.trim_end()));
+        return result
+    }
+
+    let outputs = split_outputs(&evaluation.output.unwrap_or_default(), &sentinel, examples.len());
+    let mut diffs = String::new();
+    // Output of the examples that don't match, the others are kept on --bless.
+    let mut replaced: Vec<Option<&str>> = vec![];
+    for (example, output) in examples.iter().zip(&outputs) {
+        let Some(output) = output else {
+            result.failures.push(format!("{}:{}\n{}\nthe example was not evaluated", file, example.line, example.prompt.join("\n")));
+            replaced.push(None);
+            continue
+        };
+
+        let diff = compare(&comparable(&example.expected), &comparable(&output.lines().collect::<Vec<_>>()));
+        if let Some(diff) = &diff {
+            diffs.push_str(&format!("{}:{}\n{}\n{}", file, example.line, example.prompt.join("\n"), diff));
+        }
+        replaced.push(diff.map(|_| output.as_str()));
+    }
+
+    // The session with the actual output, for --bless.
+    let mut body: Vec<&str> = vec![];
+    let mut replaced = replaced.into_iter();
+    for item in &items {
+        match item {
+            Item::Text(line) => body.push(line),
+            Item::Example(example) => {
+                body.extend(&example.prompt);
+                match replaced.next().flatten() {
+                    Some(output) => body.extend(
+                        output.lines().map(|line| if line.trim().is_empty() { BLANKLINE } else { line }),
+                    ),
+                    None => body.extend(&example.expected),
+                }
+            },
+        }
+    }
+
+    let mut session = body.join("\n");
+    if !session.is_empty() {
+        session.push('\n');
+    }
+    result.diff = Some(diffs).filter(|diffs| !diffs.is_empty());
+    result.output = Some(session.clone());
+    result.update = Some(Update::Body(session));
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let lines = vec![
+            "Setup:",
+            ">>> x = 1",
+            ">>> for i in range(2):",
+            "...     print(i + x)",
+            "...",
+            "1",
+            "2",
+            "",
+            ">>> x",
+            "1",
+        ];
+
+        let items = parse(lines, 5);
+        assert_eq!(items, vec![
+            Item::Text("Setup:"),
+            Item::Example(Example { line: 6, prompt: vec![">>> x = 1"], source: vec!["x = 1"], expected: vec![] }),
+            Item::Example(Example {
+                line: 7,
+                prompt: vec![">>> for i in range(2):", "...     print(i + x)", "..."],
+                source: vec!["for i in range(2):", "    print(i + x)", ""],
+                expected: vec!["1", "2"],
+            }),
+            Item::Text(""),
+            Item::Example(Example { line: 13, prompt: vec![">>> x"], source: vec!["x"], expected: vec!["1"] }),
+        ]);
+    }
+
+    #[test]
+    fn test_python_string() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            ("x = 1\n", "\"x = 1\\n\""),
+            ("print(\"a\\b\")", "\"print(\\\"a\\\\b\\\")\""),
+            ("\t\u{1}", "\"\\t\\u0001\""),
+        ];
+
+        for case in test_cases {
+            assert_eq!(python_string(case.0), case.1);
+        }
+    }
+
+    #[test]
+    fn test_split_outputs() {
+        let output = "S 0\nS 1\n1\n2\nS 2\n'a'\n";
+        assert_eq!(split_outputs(output, "S", 4), vec![
+            Some("".into()),
+            Some("1\n2\n".into()),
+            Some("'a'\n".into()),
+            None,
+        ]);
+    }
+
+    #[test]
+    fn test_comparable() {
+        let test_cases: Vec<(Vec<&str>, &str)> = vec![
+            (vec!["a", "<BLANKLINE>", "b"], "a\n\nb"),
+            (
+                vec![TRACEBACK, "  File \"<stdin>\", line 1, in <module>", "ZeroDivisionError: division by zero"],
+                "Traceback (most recent call last):\nZeroDivisionError: division by zero",
+            ),
+            (vec![TRACEBACK, "...", "KeyError: 'x'"], "Traceback (most recent call last):\nKeyError: 'x'"),
+        ];
+
+        for case in test_cases {
+            assert_eq!(comparable(&case.0), case.1);
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use std::{fs, path::PathBuf, str::FromStr};
+
+use serde::Serialize;
+
+use crate::error::Error;
+
+/// Format of a machine-readable report.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Format {
+    Junit,
+    Tap,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Format, Error> {
+        match value {
+            "junit" => Ok(Format::Junit),
+            "tap" => Ok(Format::Tap),
+            "json" => Ok(Format::Json),
+            _ => Err(Error::Parse(format!("unknown report format {:?}, expected junit, tap or json", value))),
+        }
+    }
+}
+
+/// A report and where it goes, stdout without a path.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Target {
+    pub format: Format,
+    pub path: Option<PathBuf>,
+}
+
+/// Parses report targets like `junit:out.xml` or `tap`.
+pub fn parse_target(value: &str) -> Result<Target, Error> {
+    let (format, path) = match value.split_once(':') {
+        Some((format, path)) => (format, Some(PathBuf::from(path)).filter(|path| path.as_os_str() != "-")),
+        None => (value, None),
+    };
+
+    Ok(Target { format: format.parse()?, path })
+}
+
+/// Results of the test cases of a document.
+#[derive(Debug, Serialize)]
+pub struct Suite {
+    pub file: String,
+    pub tests: usize,
+    pub passed: usize,
+    pub failed: usize,
+    /// Seconds.
+    pub duration: f64,
+    pub cases: Vec<CaseReport>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CaseReport {
+    /// Location and tag of the block, for example "doc.md:12 (bash)".
+    pub name: String,
+    /// The last heading before the block.
+    pub heading: Option<String>,
+    pub file: String,
+    pub line: usize,
+    pub tag: String,
+    /// "passed", "failed" or "blessed".
+    pub status: &'static str,
+    /// Seconds.
+    pub duration: f64,
+    pub exit_code: Option<i32>,
+    pub stdout: Option<String>,
+    pub stderr: Option<String>,
+    pub failures: Vec<String>,
+    pub diff: Option<String>,
+}
+
+impl CaseReport {
+    fn failed(&self) -> bool {
+        self.status == "failed"
+    }
+
+    /// Reasons of the failure with the diff.
+    fn message(&self) -> String {
+        let mut message = self.failures.join("\n");
+        if let Some(diff) = &self.diff {
+            if !message.is_empty() {
+                message.push('\n');
+            }
+            message.push_str(diff);
+        }
+
+        message
+    }
+}
+
+/// Writes the report into its file, or to stdout.
+pub fn write(target: &Target, suite: &Suite) -> Result<(), Error> {
+    let report = match target.format {
+        Format::Junit => junit(suite),
+        Format::Tap => tap(suite),
+        Format::Json => json(suite),
+    };
+
+    match &target.path {
+        Some(path) => fs::write(path, report)
+            .map_err(|err| Error::io(format!("unable to write {}", path.display()), err)),
+        None => {
+            print!("{}", report);
+            Ok(())
+        },
+    }
+}
+
+fn json(suite: &Suite) -> String {
+    let mut report = serde_json::to_string_pretty(suite).unwrap_or_default();
+    report.push('\n');
+
+    report
+}
+
+fn junit(suite: &Suite) -> String {
+    let mut report = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    report.push_str(&format!(
+        "<testsuites tests=\"{}\" failures=\"{}\" time=\"{:.3}\">\n",
+        suite.tests, suite.failed, suite.duration,
+    ));
+    report.push_str(&format!(
+        "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">\n",
+        xml_escape(&suite.file), suite.tests, suite.failed, suite.duration,
+    ));
+
+    for case in &suite.cases {
+        // Test cases are grouped by the heading of the document in CI views.
+        report.push_str(&format!(
+            "    <testcase name=\"{}\" classname=\"{}\" file=\"{}\" line=\"{}\" time=\"{:.3}\">\n",
+            xml_escape(&case.name),
+            xml_escape(case.heading.as_deref().unwrap_or(&case.file)),
+            xml_escape(&case.file),
+            case.line,
+            case.duration,
+        ));
+        if let Some(code) = case.exit_code {
+            report.push_str(&format!(
+                "      <properties><property name=\"exit_code\" value=\"{}\"/></properties>\n",
+                code,
+            ));
+        }
+        if case.failed() {
+            let message = case.failures.first().and_then(|failure| failure.lines().next()).unwrap_or("output differs");
+            report.push_str(&format!(
+                "      <failure message=\"{}\">{}</failure>\n",
+                xml_escape(message),
+                xml_escape(&case.message()),
+            ));
+        }
+        if let Some(stdout) = &case.stdout {
+            report.push_str(&format!("      <system-out>{}</system-out>\n", xml_escape(stdout)));
+        }
+        if let Some(stderr) = &case.stderr {
+            report.push_str(&format!("      <system-err>{}</system-err>\n", xml_escape(stderr)));
+        }
+        report.push_str("    </testcase>\n");
+    }
+
+    report.push_str("  </testsuite>\n</testsuites>\n");
+    report
+}
+
+/// Text in XML, characters that are not allowed in XML are dropped.
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => {},
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// TAP version 13, failures have a YAML block with the details.
+fn tap(suite: &Suite) -> String {
+    let mut report = format!("TAP version 13\n1..{}\n", suite.tests);
+
+    for (index, case) in suite.cases.iter().enumerate() {
+        let status = if case.failed() { "not ok" } else { "ok" };
+        report.push_str(&format!("{} {} - {}\n", status, index + 1, case.name));
+        if !case.failed() {
+            continue
+        }
+
+        // JSON strings are valid YAML, so the values need no other escaping.
+        report.push_str("  ---\n");
+        if let Some(heading) = &case.heading {
+            report.push_str(&format!("  heading: {}\n", json_string(heading)));
+        }
+        report.push_str(&format!("  file: {}\n", json_string(&case.file)));
+        report.push_str(&format!("  line: {}\n", case.line));
+        report.push_str(&format!("  duration_ms: {}\n", (case.duration * 1000.0).round()));
+        if let Some(code) = case.exit_code {
+            report.push_str(&format!("  exit_code: {}\n", code));
+        }
+        report.push_str(&format!("  message: {}\n", json_string(&case.message())));
+        report.push_str("  ...\n");
+    }
+
+    report
+}
+
+fn json_string(text: &str) -> String {
+    serde_json::to_string(text).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn suite() -> Suite {
+        let case = |line: usize, status: &'static str, diff: Option<&str>| CaseReport {
+            name: format!("doc.md:{} (bash)", line),
+            heading: Some("Usage & more".into()),
+            file: "doc.md".into(),
+            line,
+            tag: "bash".into(),
+            status,
+            duration: 0.25,
+            exit_code: Some(0),
+            stdout: Some("a <b>\n".into()),
+            stderr: None,
+            failures: vec![],
+            diff: diff.map(str::to_string),
+        };
+
+        Suite {
+            file: "doc.md".into(),
+            tests: 2,
+            passed: 1,
+            failed: 1,
+            duration: 0.5,
+            cases: vec![case(3, "passed", None), case(9, "failed", Some("-a\n+b\n"))],
+        }
+    }
+
+    #[test]
+    fn test_parse_target() {
+        let test_cases: Vec<(&str, Option<Target>)> = vec![
+            ("tap", Some(Target { format: Format::Tap, path: None })),
+            ("json:-", Some(Target { format: Format::Json, path: None })),
+            ("junit:out/report.xml", Some(Target { format: Format::Junit, path: Some("out/report.xml".into()) })),
+            ("html", None),
+        ];
+
+        for case in test_cases {
+            assert_eq!(parse_target(case.0).ok(), case.1);
+        }
+    }
+
+    #[test]
+    fn test_junit() {
+        let report = junit(&suite());
+
+        assert!(report.contains("<testsuite name=\"doc.md\" tests=\"2\" failures=\"1\" time=\"0.500\">"));
+        assert!(report.contains(
+            "<testcase name=\"doc.md:3 (bash)\" classname=\"Usage &amp; more\" file=\"doc.md\" line=\"3\" time=\"0.250\">"
+        ));
+        assert!(report.contains("<failure message=\"output differs\">-a\n+b\n</failure>"));
+        assert!(report.contains("<system-out>a &lt;b&gt;\n</system-out>"));
+        assert_eq!(report.matches("<failure").count(), 1);
+    }
+
+    #[test]
+    fn test_tap() {
+        let report = tap(&suite());
+
+        assert_eq!(report, concat!(
+            "TAP version 13\n",
+            "1..2\n",
+            "ok 1 - doc.md:3 (bash)\n",
+            "not ok 2 - doc.md:9 (bash)\n",
+            "  ---\n",
+            "  heading: \"Usage & more\"\n",
+            "  file: \"doc.md\"\n",
+            "  line: 9\n",
+            "  duration_ms: 250\n",
+            "  exit_code: 0\n",
+            "  message: \"-a\\n+b\\n\"\n",
+            "  ...\n",
+        ));
+    }
+
+    #[test]
+    fn test_json() {
+        let report: serde_json::Value = serde_json::from_str(&json(&suite())).unwrap();
+
+        assert_eq!(report["failed"], 1);
+        assert_eq!(report["cases"][1]["status"], "failed");
+        assert_eq!(report["cases"][1]["diff"], "-a\n+b\n");
+        assert_eq!(report["cases"][0]["heading"], "Usage & more");
+    }
+}
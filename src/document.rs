@@ -0,0 +1,333 @@
+use std::ops::Range;
+
+const FENCE_CHAR: char = '`';
+const MIN_FENCE: usize = 3;
+
+/// A fenced code block of a Markdown document with its position in the
+/// source, so the document can be rewritten without touching other bytes.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Fence {
+    /// Language tag, for example "bash" for "```bash #group=a".
+    pub tag: String,
+    /// The opening fence line.
+    pub info: String,
+    /// Line number of the opening fence (1-based).
+    pub line: usize,
+    /// Number of backticks in the fence.
+    pub ticks: usize,
+    /// Byte offset of the opening fence.
+    pub start: usize,
+    /// Bytes between the fences.
+    pub body: Range<usize>,
+    /// Byte offset after the closing fence and its newline.
+    pub end: usize,
+    /// False if the document ends before the closing fence.
+    pub closed: bool,
+}
+
+impl Fence {
+    pub fn lines<'a>(&self, source: &'a str) -> impl Iterator<Item = &'a str> {
+        source[self.body.clone()].lines()
+    }
+}
+
+/// Fenced code blocks of the document. A fence is closed by a line of at
+/// least as many backticks as the opening one.
+pub fn fences(source: &str) -> Vec<Fence> {
+    let mut fences = vec![];
+    let mut open: Option<Fence> = None;
+    let mut offset = 0;
+
+    for (index, line) in source.split_inclusive('\n').enumerate() {
+        let start = offset;
+        offset += line.len();
+        let text = line.trim_end_matches(['\n', '\r']);
+
+        if let Some(mut fence) = open.take() {
+            let closing = text.trim_end();
+            if closing.len() >= fence.ticks && closing.chars().all(|c| c == FENCE_CHAR) {
+                fence.body = fence.body.start..start;
+                fence.end = offset;
+                fence.closed = true;
+                fences.push(fence);
+            } else {
+                open = Some(fence);
+            }
+
+            continue
+        }
+
+        let Some(tag) = fence_tag(text) else {
+            continue
+        };
+        open = Some(Fence {
+            tag: tag.to_string(),
+            info: text.to_string(),
+            line: index + 1,
+            ticks: fence_ticks(text),
+            start,
+            body: offset..offset,
+            end: offset,
+            closed: false,
+        });
+    }
+
+    if let Some(mut fence) = open {
+        fence.body = fence.body.start..source.len();
+        fence.end = source.len();
+        fences.push(fence);
+    }
+
+    fences
+}
+
+/// Language tag of an opening code fence, for example "bash" for
+/// "```bash #group=a".
+pub fn fence_tag(line: &str) -> Option<&str> {
+    let ticks = fence_ticks(line);
+    if ticks < MIN_FENCE {
+        return None
+    }
+
+    let info = &line[ticks..];
+
+    info.split(|c: char| c.is_whitespace() || c == '#' || c == '{').next()
+}
+
+fn fence_ticks(line: &str) -> usize {
+    line.chars().take_while(|c| *c == FENCE_CHAR).count()
+}
+
+/// Headings of the document with their line numbers (1-based). Lines in code
+/// blocks and in the front matter are not headings.
+pub fn headings(source: &str) -> Vec<(usize, String)> {
+    let fences = fences(source);
+    let mut headings = vec![];
+    let mut offset = 0;
+    let mut front_matter = source.starts_with("---");
+
+    for (index, line) in source.split_inclusive('\n').enumerate() {
+        let start = offset;
+        offset += line.len();
+        let text = line.trim_end();
+
+        if front_matter {
+            front_matter = index == 0 || text != "---";
+            continue
+        }
+        if fences.iter().any(|fence| (fence.start..fence.end).contains(&start)) {
+            continue
+        }
+
+        let trimmed = text.trim_start_matches(' ');
+        let level = trimmed.chars().take_while(|c| *c == '#').count();
+        if text.len() - trimmed.len() > 3 || !(1..=6).contains(&level) {
+            continue
+        }
+        let title = &trimmed[level..];
+        if !title.is_empty() && !title.starts_with([' ', '\t']) {
+            continue
+        }
+
+        headings.push((index + 1, title.trim().trim_end_matches('#').trim_end().to_string()));
+    }
+
+    headings
+}
+
+/// New content of the document for a code block.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Update {
+    /// Content of the output block after the code block.
+    Output(String),
+    /// Body of the code block itself.
+    Body(String),
+}
+
+/// Updates the code blocks starting at the given lines. An output block is
+/// inserted or refreshed after the code block, it belongs to the code block if
+/// only blank lines are between them and `is_output` accepts it, new output
+/// blocks get the given tag. Everything else in the document is kept as it
+/// is, so the same update gives the same document.
+pub fn update_blocks(
+    source: &str,
+    updates: &[(usize, Update)],
+    is_output: &dyn Fn(&Fence) -> bool,
+    tag: &str,
+) -> String {
+    let fences = fences(source);
+    let mut result = String::with_capacity(source.len());
+    let mut copied = 0;
+
+    for (index, fence) in fences.iter().enumerate() {
+        let Some((_, update)) = updates.iter().find(|(line, _)| *line == fence.line) else {
+            continue
+        };
+        if !fence.closed || fence.start < copied {
+            continue
+        }
+
+        let output = match update {
+            Update::Output(output) => output,
+            Update::Body(body) => {
+                result.push_str(&source[copied..fence.body.start]);
+                result.push_str(body);
+                if !body.is_empty() && !body.ends_with('\n') {
+                    result.push('\n');
+                }
+                copied = fence.body.end;
+                continue
+            },
+        };
+
+        let existing = fences.get(index + 1).filter(|next| {
+            next.closed && is_output(next) && source[fence.end..next.start].trim().is_empty()
+        });
+
+        match existing {
+            Some(block) => {
+                result.push_str(&source[copied..block.start]);
+                result.push_str(&render_output(output, tag, Some(block)));
+                copied = block.end;
+            },
+            None => {
+                result.push_str(&source[copied..fence.end]);
+                if !source[..fence.end].ends_with('\n') {
+                    result.push('\n');
+                }
+                result.push('\n');
+                result.push_str(&render_output(output, tag, None));
+                copied = fence.end;
+            },
+        }
+    }
+
+    result.push_str(&source[copied..]);
+    result
+}
+
+/// Output block with the given content. The opening fence of the existing
+/// block is kept, unless the output contains a fence that would close it.
+fn render_output(output: &str, tag: &str, existing: Option<&Fence>) -> String {
+    let needed = output
+        .lines()
+        .map(fence_ticks)
+        .max()
+        .map_or(MIN_FENCE, |ticks| (ticks + 1).max(MIN_FENCE));
+
+    let (opening, ticks) = match existing {
+        Some(fence) if fence.ticks >= needed => (fence.info.clone(), fence.ticks),
+        _ => (format!("{}{}", "`".repeat(needed), tag), needed),
+    };
+
+    let mut block = format!("{}\n{}", opening, output);
+    if !output.is_empty() && !output.ends_with('\n') {
+        block.push('\n');
+    }
+    block.push_str(&"`".repeat(ticks));
+    block.push('\n');
+
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_output(fence: &Fence) -> bool {
+        fence.tag == "output"
+    }
+
+    #[test]
+    fn test_fence_tag() {
+        let test_cases: Vec<(&str, Option<&str>)> = vec![
+            ("```bash", Some("bash")),
+            ("```bash #group=a", Some("bash")),
+            ("```c", Some("c")),
+            ("```python{.numberLines}", Some("python")),
+            ("````output", Some("output")),
+            ("```", Some("")),
+            ("``inline``", None),
+            ("echo", None),
+        ];
+
+        for case in test_cases {
+            assert_eq!(fence_tag(case.0), case.1);
+        }
+    }
+
+    #[test]
+    fn test_fences() {
+        let source = "# Doc\n\n```bash #group=a\necho a\n```\n\n````md\n```\nnested\n```\n````\n\n```sh\nunclosed";
+        let fences = fences(source);
+
+        assert_eq!(fences.len(), 3);
+        assert_eq!(fences[0].tag, "bash");
+        assert_eq!(fences[0].info, "```bash #group=a");
+        assert_eq!(fences[0].line, 3);
+        assert_eq!(&source[fences[0].start..fences[0].end], "```bash #group=a\necho a\n```\n");
+        assert_eq!(fences[0].lines(source).collect::<Vec<_>>(), vec!["echo a"]);
+
+        assert_eq!(fences[1].tag, "md");
+        assert_eq!(fences[1].ticks, 4);
+        assert_eq!(&source[fences[1].body.clone()], "```\nnested\n```\n");
+
+        assert!(!fences[2].closed);
+        assert_eq!(&source[fences[2].body.clone()], "unclosed");
+    }
+
+    #[test]
+    fn test_headings() {
+        let source = "---\ntitle: x\n# comment\n---\n# Title\n\n```bash\n# not a heading\n```\n\n## Usage ##\n#hashtag\n    # code\n";
+
+        assert_eq!(headings(source), vec![(5, "Title".to_string()), (11, "Usage".to_string())]);
+    }
+
+    #[test]
+    fn test_update_blocks_insert() {
+        let source = "# Doc\n\n```bash\necho a\n```\nText\n";
+        let updated = update_blocks(source, &[(3, Update::Output("a\n".into()))], &is_output, "output");
+
+        assert_eq!(updated, "# Doc\n\n```bash\necho a\n```\n\n```output\na\n```\nText\n");
+        assert_eq!(update_blocks(&updated, &[(3, Update::Output("a\n".into()))], &is_output, "output"), updated);
+    }
+
+    #[test]
+    fn test_update_blocks_refresh() {
+        let source = "```bash\necho b\n```\n\n```output #keep\nold\n```\n\n```output\nother\n```";
+        let updated = update_blocks(source, &[(1, Update::Output("b\n".into()))], &is_output, "output");
+
+        assert_eq!(updated, "```bash\necho b\n```\n\n```output #keep\nb\n```\n\n```output\nother\n```");
+        assert_eq!(update_blocks(&updated, &[(1, Update::Output("b\n".into()))], &is_output, "output"), updated);
+    }
+
+    #[test]
+    fn test_update_blocks_body() {
+        let source = "```console\n$ echo a\nb\n```\n\n```bash\necho\n```\n";
+        let updates = vec![(1, Update::Body("$ echo a\na".into()))];
+        let updated = update_blocks(source, &updates, &is_output, "output");
+
+        assert_eq!(updated, "```console\n$ echo a\na\n```\n\n```bash\necho\n```\n");
+        assert_eq!(update_blocks(&updated, &updates, &is_output, "output"), updated);
+    }
+
+    #[test]
+    fn test_update_blocks_edge_cases() {
+        let test_cases: Vec<(&str, &str, &str)> = vec![
+            // Code block at the end, without a newline.
+            ("```bash\necho\n```", "", "```bash\necho\n```\n\n```output\n```\n"),
+            // Output without a trailing newline.
+            ("```bash\nprintf x\n```\n", "x", "```bash\nprintf x\n```\n\n```output\nx\n```\n"),
+            // Output with a fence needs a longer fence.
+            ("```bash\necho\n```\n", "```\n", "```bash\necho\n```\n\n````output\n```\n````\n"),
+            // Text between the blocks, the output block is not adjacent.
+            ("```bash\necho\n```\ntext\n```output\nx\n```\n", "y\n", "```bash\necho\n```\n\n```output\ny\n```\ntext\n```output\nx\n```\n"),
+        ];
+
+        for case in test_cases {
+            let updated = update_blocks(case.0, &[(1, Update::Output(case.1.into()))], &is_output, "output");
+            assert_eq!(updated, case.2);
+            assert_eq!(update_blocks(&updated, &[(1, Update::Output(case.1.into()))], &is_output, "output"), updated);
+        }
+    }
+}
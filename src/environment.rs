@@ -0,0 +1,147 @@
+use std::fs;
+
+use crate::error::Error;
+
+/// Variables kept from the parent environment with `--clean-env`.
+pub const DEFAULT_ALLOWLIST: &[&str] = &[
+    "HOME",
+    "LANG",
+    "LOGNAME",
+    "PATH",
+    "SHELL",
+    "SYSTEMROOT",
+    "TERM",
+    "TMPDIR",
+    "USER",
+];
+
+/// Parses a `KEY=VAL` pair.
+pub fn parse_pair(value: &str) -> Result<(String, String), Error> {
+    match value.split_once('=') {
+        Some((key, value)) if valid_key(key) => Ok((key.to_string(), value.to_string())),
+        _ => Err(Error::Parse(format!("invalid environment variable, expected KEY=VAL: {:?}", value))),
+    }
+}
+
+pub fn read_env_file(path: &str) -> Result<Vec<(String, String)>, Error> {
+    let content = fs::read_to_string(path)
+        .map_err(|err| Error::io(format!("unable to read {}", path), err))?;
+
+    parse_dotenv(&content).map_err(|err| Error::Parse(format!("{}: {}", path, err)))
+}
+
+/// Parses the content of a `.env` file. Supported syntax:
+///
+/// ```text
+/// # comment
+/// KEY=value
+/// export KEY=value # inline comment
+/// KEY="double quoted\nwith escapes"
+/// KEY='single quoted, taken as is'
+/// ```
+pub fn parse_dotenv(content: &str) -> Result<Vec<(String, String)>, Error> {
+    let mut vars = vec![];
+
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) if valid_key(key.trim()) => (key.trim(), value.trim()),
+            _ => return Err(Error::Parse(format!("line {}: expected KEY=VAL", index + 1))),
+        };
+
+        vars.push((key.to_string(), dotenv_value(value)));
+    }
+
+    Ok(vars)
+}
+
+fn dotenv_value(value: &str) -> String {
+    if let Some(rest) = value.strip_prefix('\'') {
+        return rest.split_once('\'').map(|(v, _)| v).unwrap_or(rest).to_string()
+    }
+
+    if let Some(rest) = value.strip_prefix('"') {
+        let mut result = String::new();
+        let mut chars = rest.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => break,
+                '\\' => match chars.next() {
+                    Some('n') => result.push('\n'),
+                    Some('t') => result.push('\t'),
+                    Some(other) => result.push(other),
+                    None => break,
+                },
+                _ => result.push(c),
+            }
+        }
+        return result
+    }
+
+    match value.find(" #") {
+        Some(index) => value[..index].trim_end().to_string(),
+        None => value.to_string(),
+    }
+}
+
+fn valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && !key.starts_with(|c: char| c.is_ascii_digit())
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pair() {
+        let test_cases: Vec<(&str, Option<(&str, &str)>)> = vec![
+            ("KEY=value", Some(("KEY", "value"))),
+            ("KEY=", Some(("KEY", ""))),
+            ("KEY=a=b", Some(("KEY", "a=b"))),
+            ("KEY", None),
+            ("=value", None),
+            ("1KEY=value", None),
+        ];
+
+        for case in test_cases {
+            let result = parse_pair(case.0).ok();
+            assert_eq!(result, case.1.map(|(k, v)| (k.to_string(), v.to_string())));
+        }
+    }
+
+    #[test]
+    fn test_parse_dotenv() {
+        let content = r#"
+# comment
+PLAIN=value
+export EXPORTED=yes
+INLINE=value # comment
+DOUBLE="line 1\nline 2" # comment
+SINGLE='raw\n # value'
+EMPTY=
+"#;
+
+        let expected: Vec<(String, String)> = vec![
+            ("PLAIN", "value"),
+            ("EXPORTED", "yes"),
+            ("INLINE", "value"),
+            ("DOUBLE", "line 1\nline 2"),
+            ("SINGLE", "raw\\n # value"),
+            ("EMPTY", ""),
+        ].into_iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+
+        assert_eq!(parse_dotenv(content).ok(), Some(expected));
+    }
+
+    #[test]
+    fn test_parse_dotenv_error() {
+        assert!(parse_dotenv("VALID=1\ninvalid line\n").is_err());
+    }
+}
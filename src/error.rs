@@ -0,0 +1,116 @@
+use std::{fmt, io, time::Duration};
+
+use crate::timeout::format_duration;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The requested language or executor is not known.
+    UnknownLanguage(String),
+    /// The interpreter binary does not exist or it's not in PATH.
+    InterpreterNotFound {
+        binary: String,
+        alternatives: Vec<&'static str>,
+    },
+    /// The interpreter exists, but it could not be started.
+    Spawn {
+        binary: String,
+        source: io::Error,
+    },
+    /// Reading or writing a file or a stream failed.
+    Io {
+        context: String,
+        source: io::Error,
+    },
+    /// Invalid input, for example a malformed option value.
+    Parse(String),
+    /// The feature is not available for the language.
+    Unsupported(String),
+    /// The code could not be compiled, the output of the compiler is
+    /// included.
+    Compile {
+        binary: String,
+        output: String,
+    },
+    /// The script did not finish in time and it was terminated.
+    Timeout {
+        target: String,
+        limit: Duration,
+    },
+}
+
+impl Error {
+    pub fn io<S: Into<String>>(context: S, source: io::Error) -> Error {
+        Error::Io { context: context.into(), source }
+    }
+
+    /// Exit code reported to the caller, follows the conventions of common
+    /// shells where it makes sense (127 for command not found, 126 for not
+    /// executable).
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::UnknownLanguage(_) | Error::Parse(_) | Error::Unsupported(_) => 2,
+            Error::InterpreterNotFound { .. } => 127,
+            Error::Spawn { .. } => 126,
+            Error::Io { .. } | Error::Compile { .. } => 1,
+            Error::Timeout { .. } => 124,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnknownLanguage(lang) => write!(f, "unknown language: {}", lang),
+            Error::InterpreterNotFound { binary, .. } => write!(f, "interpreter not found: {}", binary),
+            Error::Spawn { binary, source } => write!(f, "failed to start {}: {}", binary, source),
+            Error::Io { context, source } => write!(f, "{}: {}", context, source),
+            Error::Parse(msg) | Error::Unsupported(msg) => write!(f, "{}", msg),
+            Error::Compile { binary, output } => write!(f, "compilation failed with {}:\n{}", binary, output.trim_end()),
+            Error::Timeout { target, limit } => write!(f, "{} timed out after {}", target, format_duration(*limit)),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Spawn { source, .. } | Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_code() {
+        let test_cases: Vec<(Error, u8)> = vec![
+            (Error::UnknownLanguage("x".into()), 2),
+            (Error::Parse("x".into()), 2),
+            (Error::Unsupported("x".into()), 2),
+            (Error::InterpreterNotFound { binary: "x".into(), alternatives: vec![] }, 127),
+            (Error::Spawn { binary: "x".into(), source: io::ErrorKind::PermissionDenied.into() }, 126),
+            (Error::io("x", io::ErrorKind::NotFound.into()), 1),
+            (Error::Compile { binary: "x".into(), output: "x".into() }, 1),
+            (Error::Timeout { target: "x".into(), limit: Duration::from_secs(1) }, 124),
+        ];
+
+        for case in test_cases {
+            assert_eq!(case.0.exit_code(), case.1);
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        let err = Error::InterpreterNotFound { binary: "deno".into(), alternatives: vec!["node"] };
+        assert_eq!(err.to_string(), "interpreter not found: deno");
+
+        let err = Error::io("unable to read example.md", io::ErrorKind::NotFound.into());
+        assert_eq!(err.to_string(), "unable to read example.md: entity not found");
+
+        let err = Error::Timeout { target: "block at example.md:12".into(), limit: Duration::from_secs(30) };
+        assert_eq!(err.to_string(), "block at example.md:12 timed out after 30s");
+    }
+}
@@ -0,0 +1,82 @@
+use std::path::Path;
+
+use crate::code_container::CodeContainer;
+
+use super::{script_arg, Executor};
+
+pub struct Awk;
+
+impl Executor for Awk {
+    fn export(&self, script: CodeContainer) -> String {
+        let mut header: Vec<String> = vec![
+            "#!/usr/bin/env -S awk -f".into(),
+            "".into(),
+        ];
+
+        header.push(script.lines());
+
+        header.join("\n")
+    }
+
+    fn binary(&self) -> &'static str {
+        "awk"
+    }
+
+    fn args(&self, script: Option<&Path>, args: Vec<String>) -> Vec<String> {
+        // The arguments are input files or variable assignments, like with
+        // `awk -f script.awk`.
+        let mut argv = vec!["-f".to_string(), script_arg(script, "/dev/stdin")];
+        argv.extend(args);
+        argv
+    }
+
+    fn extension(&self) -> &'static str {
+        ".awk"
+    }
+}
+
+impl Awk {
+    pub fn new() -> Awk {
+        Awk{}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export() {
+        let mut code = CodeContainer::new();
+        code.open_new_group();
+        code.push("BEGIN { print \"check\" }".into());
+        code.close_group();
+
+        let lang = Awk::new();
+        let output = lang.export(code);
+        let expected_output = "#!/usr/bin/env -S awk -f\n\nBEGIN { print \"check\" }".to_string();
+        assert_eq!(output, expected_output);
+    }
+
+    #[test]
+    fn test_args() {
+        let lang = Awk::new();
+        let args = lang.args(None, vec!["--my-flag".into(), "-o".into(), "file".into()]);
+        let expected_args = vec!["-f", "/dev/stdin", "--my-flag", "-o", "file"];
+        assert_eq!(args, expected_args);
+    }
+
+    #[test]
+    fn test_file_args() {
+        let lang = Awk::new();
+        let args = lang.args(Some(Path::new("/tmp/script.awk")), vec!["--my-flag".into()]);
+        let expected_args = vec!["-f", "/tmp/script.awk", "--my-flag"];
+        assert_eq!(args, expected_args);
+    }
+
+    #[test]
+    fn test_awk_binary() {
+        let lang = Awk::new();
+        assert_eq!(lang.binary(), "awk")
+    }
+}
@@ -0,0 +1,359 @@
+use std::{env, fs, io::{Read, Seek, SeekFrom}, path::{Path, PathBuf}, process::{Command, Stdio}, time::Instant};
+
+use crate::code_container::CodeContainer;
+use crate::error::Error;
+use crate::timeout;
+
+use super::{process, Context, Executor, Job, Process};
+
+/// Languages that have to be compiled before evaluation. The compiled binary
+/// is cached by the hash of the source code, so the same code is compiled only
+/// once.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Compiled {
+    C,
+    Cpp,
+    Go,
+    Rust,
+}
+
+impl Executor for Compiled {
+    fn exec(&self, script: CodeContainer, argv: Vec<String>, ctx: &Context) -> Result<Process, Error> {
+        let binary = self.build(&script, ctx)?;
+
+        let mut command = Command::new(&binary);
+        command.args(argv);
+
+        process::start(command, ctx).map(|child| Process::new(child, ctx))
+    }
+
+    fn export(&self, script: CodeContainer) -> String {
+        self.source(&script.lines())
+    }
+
+    fn binary(&self) -> &'static str {
+        match self {
+            Compiled::C => "cc",
+            Compiled::Cpp => "c++",
+            Compiled::Go => "go",
+            Compiled::Rust => "rustc",
+        }
+    }
+
+    fn args(&self, _script: Option<&Path>, args: Vec<String>) -> Vec<String> {
+        args
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            Compiled::C => ".c",
+            Compiled::Cpp => ".cpp",
+            Compiled::Go => ".go",
+            Compiled::Rust => ".rs",
+        }
+    }
+}
+
+impl Compiled {
+    /// Complete source file from the code blocks. Rust code without a main
+    /// function is wrapped into one (like in rustdoc), and Go code gets a
+    /// package clause if it doesn't have one.
+    fn source(&self, code: &str) -> String {
+        let (header, footer) = self.wrapper(code);
+
+        format!("{}{}\n{}", header, code, footer)
+    }
+
+    fn wrapper(&self, code: &str) -> (&'static str, &'static str) {
+        match self {
+            Compiled::Rust if !code.contains("fn main") => ("fn main() {\n", "}\n"),
+            Compiled::Go if !code.lines().any(|l| l.trim_start().starts_with("package ")) => ("package main\n\n", ""),
+            _ => ("", ""),
+        }
+    }
+
+    fn compile_args(&self, source: &str, output: &Path) -> Vec<String> {
+        let output = output.to_string_lossy().to_string();
+
+        match self {
+            Compiled::C | Compiled::Cpp => vec!["-o".into(), output, source.into()],
+            Compiled::Go => vec!["build".into(), "-o".into(), output, source.into()],
+            Compiled::Rust => vec!["--edition=2021".into(), "-o".into(), output, source.into()],
+        }
+    }
+
+    fn version_args(&self) -> Vec<String> {
+        match self {
+            Compiled::Go => vec!["version".into()],
+            Compiled::C | Compiled::Cpp | Compiled::Rust => vec!["--version".into()],
+        }
+    }
+
+    /// Version of the compiler, so an upgraded compiler doesn't get the
+    /// binaries of the previous one from the cache.
+    fn version(&self, ctx: &Context) -> Result<String, Error> {
+        let mut command = Command::new(self.binary());
+        command.args(self.version_args());
+        ctx.apply(&mut command);
+        command.stdin(Stdio::null());
+
+        let output = command.output().map_err(|err| process::spawn_error(&command, err))?;
+
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    /// Compiles the code, or returns the cached binary if the same code was
+    /// compiled before with the same compiler. The compiler is terminated at
+    /// the deadline of the script.
+    fn build(&self, script: &CodeContainer, ctx: &Context) -> Result<PathBuf, Error> {
+        let code = script.lines();
+        let source = self.source(&code);
+
+        let key = hash(&[self.binary(), &self.version(ctx)?, &source]);
+        let cache = ctx.build_cache.clone().unwrap_or_else(cache_dir).join(format!("{:016x}", key));
+        let binary = cache.join(format!("main{}", env::consts::EXE_SUFFIX));
+        if binary.is_file() {
+            return Ok(binary)
+        }
+
+        fs::create_dir_all(&cache)
+            .map_err(|err| Error::io(format!("unable to create {}", cache.display()), err))?;
+
+        let project = tempfile::Builder::new()
+            .prefix("eval-md-build-")
+            .tempdir()
+            .map_err(|err| Error::io("unable to create temporary build directory", err))?;
+        let file_name = format!("main{}", self.extension());
+        fs::write(project.path().join(&file_name), &source)
+            .map_err(|err| Error::io(format!("unable to write {}", file_name), err))?;
+
+        // Build into the project first, so a failed or interrupted build
+        // doesn't leave a broken binary in the cache.
+        let output = project.path().join(binary.file_name().unwrap_or_default());
+        let mut command = Command::new(self.binary());
+        command.args(self.compile_args(&file_name, &output));
+        ctx.apply(&mut command);
+        command.current_dir(project.path());
+
+        // Both streams go into the same file, so a compiler with a lot of
+        // messages can't block on a full pipe.
+        let mut messages = tempfile::tempfile()
+            .map_err(|err| Error::io("unable to create temporary output file", err))?;
+        let stdout = messages.try_clone().map_err(|err| Error::io("unable to capture the output", err))?;
+        let stderr = messages.try_clone().map_err(|err| Error::io("unable to capture the output", err))?;
+        command.stdin(Stdio::null()).stdout(stdout).stderr(stderr);
+
+        let mut child = command.spawn().map_err(|err| process::spawn_error(&command, err))?;
+        let _job = Job::new(&child, ctx);
+        let limit = ctx.deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        let status = timeout::wait(&mut child, limit)
+            .map_err(|err| Error::io(format!("failed to wait for {}", self.binary()), err))?;
+
+        let Some(status) = status else {
+            return Err(Error::Timeout {
+                target: format!("compilation with {}", self.binary()),
+                limit: limit.unwrap_or_default(),
+            })
+        };
+        if !status.success() {
+            let prefix_lines = self.wrapper(&code).0.lines().count();
+            let mut output = vec![];
+            messages.seek(SeekFrom::Start(0))
+                .and_then(|_| messages.read_to_end(&mut output))
+                .map_err(|err| Error::io("unable to read the compiler output", err))?;
+
+            return Err(Error::Compile {
+                binary: self.binary().to_string(),
+                output: map_lines(&String::from_utf8_lossy(&output), &file_name, &ctx.document, |line| {
+                    line.checked_sub(prefix_lines).and_then(|line| script.source_line(line))
+                }),
+            })
+        }
+
+        fs::rename(&output, &binary)
+            .or_else(|_| fs::copy(&output, &binary).map(|_| ()))
+            .map_err(|err| Error::io(format!("unable to write {}", binary.display()), err))?;
+
+        Ok(binary)
+    }
+}
+
+fn cache_dir() -> PathBuf {
+    let base = match (env::var_os("XDG_CACHE_HOME"), env::var_os("HOME")) {
+        (Some(dir), _) if !dir.is_empty() => PathBuf::from(dir),
+        (_, Some(home)) => PathBuf::from(home).join(".cache"),
+        _ => env::temp_dir(),
+    };
+
+    base.join("eval-md").join("build")
+}
+
+/// 64-bit FNV-1a hash, stable across versions and platforms unlike the
+/// hasher in the standard library.
+pub fn hash(parts: &[&str]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+
+    for part in parts {
+        for byte in part.bytes().chain([0]) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    hash
+}
+
+/// Replaces `file:LINE` references in compiler messages with the location in
+/// the Markdown document.
+fn map_lines<F>(output: &str, file_name: &str, document: &Path, map: F) -> String
+where F: Fn(usize) -> Option<usize> {
+    let pattern = format!("{}:", file_name);
+    let mut result = String::new();
+    let mut rest = output;
+
+    while let Some(index) = rest.find(&pattern) {
+        let (before, after) = rest.split_at(index);
+        let after = &after[pattern.len()..];
+        let digits = after.find(|c: char| !c.is_ascii_digit()).unwrap_or(after.len());
+
+        match after[..digits].parse::<usize>().ok().and_then(&map) {
+            Some(line) => {
+                result.push_str(before.strip_suffix("./").unwrap_or(before));
+                result.push_str(&format!("{}:{}", document.display(), line));
+                rest = &after[digits..];
+            },
+            None => {
+                result.push_str(before);
+                result.push_str(&pattern);
+                rest = after;
+            },
+        }
+    }
+
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code(lines: &[&str]) -> CodeContainer {
+        let mut code = CodeContainer::new();
+        code.open_block(10, "", vec![]);
+        for line in lines {
+            code.push(line.to_string());
+        }
+        code.close_group();
+        code
+    }
+
+    #[test]
+    fn test_rust_export() {
+        let lang = Compiled::Rust;
+
+        let output = lang.export(code(&["println!(\"check\");"]));
+        assert_eq!(output, "fn main() {\nprintln!(\"check\");\n}\n");
+
+        let output = lang.export(code(&["fn main() {", "    println!(\"check\");", "}"]));
+        assert_eq!(output, "fn main() {\n    println!(\"check\");\n}\n");
+    }
+
+    #[test]
+    fn test_go_export() {
+        let lang = Compiled::Go;
+
+        let output = lang.export(code(&["func main() {}"]));
+        assert_eq!(output, "package main\n\nfunc main() {}\n");
+
+        let output = lang.export(code(&["package main", "func main() {}"]));
+        assert_eq!(output, "package main\nfunc main() {}\n");
+    }
+
+    #[test]
+    fn test_c_export() {
+        let output = Compiled::C.export(code(&["int main() { return 0; }"]));
+        assert_eq!(output, "int main() { return 0; }\n");
+    }
+
+    #[test]
+    fn test_args() {
+        let lang = Compiled::Go;
+        let args = lang.args(None, vec!["--my-flag".into(), "-o".into(), "file".into()]);
+        let expected_args = vec!["--my-flag", "-o", "file"];
+        assert_eq!(args, expected_args);
+    }
+
+    #[test]
+    fn test_compile_args() {
+        let output = Path::new("/tmp/out");
+        let test_cases: Vec<(Compiled, Vec<&str>)> = vec![
+            (Compiled::C, vec!["-o", "/tmp/out", "main.c"]),
+            (Compiled::Cpp, vec!["-o", "/tmp/out", "main.cpp"]),
+            (Compiled::Go, vec!["build", "-o", "/tmp/out", "main.go"]),
+            (Compiled::Rust, vec!["--edition=2021", "-o", "/tmp/out", "main.rs"]),
+        ];
+
+        for case in test_cases {
+            let source = format!("main{}", case.0.extension());
+            assert_eq!(case.0.compile_args(&source, output), case.1);
+        }
+    }
+
+    #[test]
+    fn test_build_deadline() {
+        if Command::new("cc").arg("--version").output().is_err() {
+            return
+        }
+
+        let cache = tempfile::tempdir().unwrap();
+        let ctx = Context {
+            process_group: true,
+            deadline: Some(Instant::now()),
+            build_cache: Some(cache.path().to_path_buf()),
+            ..Context::default()
+        };
+        let result = Compiled::C.build(&code(&["int main() { return 0; }", "// deadline"]), &ctx);
+
+        assert!(matches!(result, Err(Error::Timeout { .. })));
+    }
+
+    #[test]
+    fn test_build_cache() {
+        if Command::new("cc").arg("--version").output().is_err() {
+            return
+        }
+
+        let cache = tempfile::tempdir().unwrap();
+        let ctx = Context { build_cache: Some(cache.path().to_path_buf()), ..Context::default() };
+        let binary = Compiled::C.build(&code(&["int main() { return 0; }"]), &ctx).unwrap();
+
+        assert!(binary.starts_with(cache.path()));
+        assert_eq!(Compiled::C.build(&code(&["int main() { return 0; }"]), &ctx).unwrap(), binary);
+    }
+
+    #[test]
+    fn test_hash() {
+        assert_eq!(hash(&["rustc", "fn main() {}"]), hash(&["rustc", "fn main() {}"]));
+        assert_ne!(hash(&["rustc", "fn main() {}"]), hash(&["cc", "fn main() {}"]));
+        assert_ne!(hash(&["ab", "c"]), hash(&["a", "bc"]));
+    }
+
+    #[test]
+    fn test_map_lines() {
+        let map = |line: usize| if line <= 3 { Some(line + 20) } else { None };
+        let document = Path::new("doc.md");
+
+        let test_cases: Vec<(&str, &str, &str)> = vec![
+            ("main.c", "main.c:2:5: error: x", "doc.md:22:5: error: x"),
+            ("main.go", "./main.go:3:1: undefined: x", "doc.md:23:1: undefined: x"),
+            ("main.rs", "  --> main.rs:1:5\n   |", "  --> doc.md:21:5\n   |"),
+            ("main.rs", "main.rs:9:1 and main.rs:1", "main.rs:9:1 and doc.md:21"),
+            ("main.rs", "main.rs: nothing", "main.rs: nothing"),
+        ];
+
+        for case in test_cases {
+            assert_eq!(map_lines(case.1, case.0, document, map), case.2);
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use std::{fs::File, path::PathBuf, process::Command, sync::Arc, time::Instant};
+
+/// How the script is passed to the interpreter.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Input {
+    /// Write the script into a temporary file and pass its path, stdin is
+    /// left for the script.
+    #[default]
+    File,
+    /// Pipe the script into the interpreter's stdin.
+    Stdin,
+}
+
+/// Execution settings shared by all executors, applied on the interpreter
+/// command before it starts.
+#[derive(Debug, Default, Clone)]
+pub struct Context {
+    /// Start the interpreter in its own process group, so it can be terminated
+    /// together with its children, see [`Job`](super::Job).
+    pub process_group: bool,
+    /// Start from an empty environment, only variables in `keep_env` are
+    /// inherited.
+    pub clean_env: bool,
+    pub keep_env: Vec<String>,
+    /// Extra environment variables, later entries override earlier ones.
+    pub env: Vec<(String, String)>,
+    /// Working directory of the interpreter.
+    pub cwd: Option<PathBuf>,
+    pub input: Input,
+    /// Database of SQL code blocks.
+    pub database: Option<PathBuf>,
+    /// Python virtual environment.
+    pub venv: Option<PathBuf>,
+    /// Write stdout and stderr of the script into this file instead of the
+    /// terminal.
+    pub capture: Option<Arc<File>>,
+    /// Write stderr of the script into this file instead, so it can be
+    /// checked on its own.
+    pub capture_stderr: Option<Arc<File>>,
+    /// Path of the Markdown file, used in messages.
+    pub document: PathBuf,
+    /// End of the time limit of the script, a build before it counts too.
+    pub deadline: Option<Instant>,
+    /// Cache of compiled binaries, `~/.cache/eval-md/build` if it's not set.
+    pub build_cache: Option<PathBuf>,
+}
+
+impl Context {
+    pub fn apply(&self, command: &mut Command) {
+        if self.process_group {
+            super::job::prepare(command);
+        }
+
+        if self.clean_env {
+            command.env_clear();
+            for key in &self.keep_env {
+                if let Some(value) = std::env::var_os(key) {
+                    command.env(key, value);
+                }
+            }
+        }
+
+        command.envs(self.env.iter().map(|(k, v)| (k, v)));
+
+        if let Some(cwd) = &self.cwd {
+            command.current_dir(cwd);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn envs(command: &Command) -> Vec<(String, Option<String>)> {
+        command
+            .get_envs()
+            .map(|(k, v)| (k.to_string_lossy().into(), v.map(|v| v.to_string_lossy().into())))
+            .collect()
+    }
+
+    #[test]
+    fn test_apply_env() {
+        let ctx = Context {
+            env: vec![("A".into(), "1".into()), ("B".into(), "2".into()), ("A".into(), "3".into())],
+            ..Context::default()
+        };
+
+        let mut command = Command::new("true");
+        ctx.apply(&mut command);
+
+        assert_eq!(envs(&command), vec![("A".into(), Some("3".into())), ("B".into(), Some("2".into()))]);
+    }
+
+    #[test]
+    fn test_apply_clean_env() {
+        let ctx = Context {
+            clean_env: true,
+            keep_env: vec!["EVAL_MD_SURELY_NOT_SET".into()],
+            env: vec![("A".into(), "1".into())],
+            ..Context::default()
+        };
+
+        let mut command = Command::new("true");
+        command.env("LEAKED", "yes");
+        ctx.apply(&mut command);
+
+        assert_eq!(envs(&command), vec![("A".into(), Some("1".into()))]);
+    }
+
+    #[test]
+    fn test_apply_cwd() {
+        let ctx = Context {
+            cwd: Some(PathBuf::from("example")),
+            ..Context::default()
+        };
+
+        let mut command = Command::new("true");
+        ctx.apply(&mut command);
+
+        assert_eq!(command.get_current_dir(), Some(PathBuf::from("example").as_path()));
+    }
+}
@@ -0,0 +1,318 @@
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::code_container::CodeContainer;
+use crate::comment::Comment;
+use crate::error::Error;
+
+use super::{process, Context, Executor, Input, Process};
+
+const PLACEHOLDER_SCRIPT: &str = "{script}";
+const PLACEHOLDER_FILE: &str = "{file}";
+const PLACEHOLDER_ARGS: &str = "{args}";
+
+/// Language defined in a configuration file.
+///
+/// ```toml
+/// [languages.fennel]
+/// binary = "fennel"
+/// args = ["{script}", "{args}"]
+/// input = "file"
+/// shebang = "#!/usr/bin/env fennel"
+/// extension = ".fnl"
+/// comment = ";;"
+/// aliases = ["fnl"]
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Custom {
+    #[serde(skip)]
+    pub name: String,
+    pub binary: String,
+    /// Arguments of the interpreter. `{script}` is replaced with the path of
+    /// the script (or `-` with stdin input), `{args}` with the arguments of the
+    /// script.
+    #[serde(default = "default_args")]
+    pub args: Vec<String>,
+    /// Overrides the input mode from the command line.
+    pub input: Option<Input>,
+    /// Header of the exported script, `#!/usr/bin/env <binary>` by default.
+    /// Empty string means no header.
+    pub shebang: Option<String>,
+    #[serde(default)]
+    pub extension: String,
+    /// Line comment prefix for `--annotate`, the comments of the tag with the
+    /// same name by default. Empty string means no comments.
+    pub comment: Option<String>,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+}
+
+fn default_args() -> Vec<String> {
+    vec![PLACEHOLDER_SCRIPT.into(), PLACEHOLDER_ARGS.into()]
+}
+
+impl Executor for Custom {
+    fn exec(&self, script: CodeContainer, argv: Vec<String>, ctx: &Context) -> Result<Process, Error> {
+        let mut ctx = ctx.clone();
+        ctx.input = self.input.unwrap_or(ctx.input);
+
+        process::spawn(self, script.lines(), argv, &ctx)
+    }
+
+    fn export(&self, script: CodeContainer) -> String {
+        let shebang = match &self.shebang {
+            Some(shebang) if shebang.is_empty() => return script.lines(),
+            Some(shebang) => shebang.clone(),
+            None => format!("#!/usr/bin/env {}", self.binary),
+        };
+
+        [shebang, "".into(), script.lines()].join("\n")
+    }
+
+    fn binary(&self) -> &str {
+        &self.binary
+    }
+
+    fn args(&self, script: Option<&Path>, args: Vec<String>) -> Vec<String> {
+        let script = super::script_arg(script, "-");
+        let mut argv = vec![];
+
+        for part in &self.args {
+            if part == PLACEHOLDER_ARGS {
+                argv.extend(args.clone());
+            } else {
+                argv.push(part.replace(PLACEHOLDER_SCRIPT, &script));
+            }
+        }
+
+        argv
+    }
+
+    fn extension(&self) -> &str {
+        &self.extension
+    }
+
+    fn comment(&self) -> Option<Comment> {
+        match &self.comment {
+            Some(start) if start.is_empty() => None,
+            Some(start) => Some(Comment::line_prefix(start)),
+            None => Comment::for_tag(&self.name),
+        }
+    }
+}
+
+impl Custom {
+    pub fn new<S: Into<String>>(name: S, binary: S) -> Custom {
+        Custom {
+            name: name.into(),
+            binary: binary.into(),
+            args: default_args(),
+            input: None,
+            shebang: None,
+            extension: String::new(),
+            comment: None,
+            aliases: vec![],
+        }
+    }
+
+    /// Ad-hoc executor from a command line, for example `jq -f {file}`. With
+    /// a `{file}` (or `{script}`) placeholder the code is passed as a file,
+    /// otherwise it's piped into stdin. Arguments to the script are appended
+    /// at the end, unless there is an `{args}` placeholder.
+    pub fn from_command(tag: &str, command: &str) -> Result<Custom, Error> {
+        let mut parts = split_command(command)?.into_iter();
+        let binary = parts
+            .next()
+            .ok_or_else(|| Error::Parse("empty command for the executor".into()))?;
+
+        let mut custom = Custom::new("exec".to_string(), binary);
+        custom.args = parts.map(|p| p.replace(PLACEHOLDER_FILE, PLACEHOLDER_SCRIPT)).collect();
+
+        custom.resolve_input()?;
+        if !custom.args.iter().any(|a| a == PLACEHOLDER_ARGS) {
+            custom.args.push(PLACEHOLDER_ARGS.into());
+        }
+        if !tag.is_empty() && tag.chars().all(|c| c.is_ascii_alphanumeric()) {
+            custom.extension = format!(".{}", tag);
+        }
+        custom.comment = Comment::for_tag(tag).filter(|comment| comment.end.is_empty()).map(|comment| comment.start);
+
+        Ok(custom)
+    }
+
+    /// Without a `{script}` placeholder the interpreter can only get the code
+    /// on stdin, so the input is stdin, file input is an error.
+    pub fn resolve_input(&mut self) -> Result<(), Error> {
+        if self.args.iter().any(|a| a.contains(PLACEHOLDER_SCRIPT)) {
+            return Ok(())
+        }
+
+        match self.input {
+            Some(Input::File) => Err(Error::Parse(format!(
+                "{}: input = \"file\" needs a {} placeholder in args",
+                self.name,
+                PLACEHOLDER_SCRIPT,
+            ))),
+            _ => {
+                self.input = Some(Input::Stdin);
+                Ok(())
+            },
+        }
+    }
+}
+
+/// Splits a command line into words, with support for single and double
+/// quotes and backslash escapes, similar to a POSIX shell.
+fn split_command(command: &str) -> Result<Vec<String>, Error> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut chars = command.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' | '\n' => {
+                if let Some(word) = word.take() {
+                    words.push(word);
+                }
+            },
+            '\'' => {
+                let current = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => current.push(c),
+                        None => return Err(Error::Parse(format!("unterminated quote in {:?}", command))),
+                    }
+                }
+            },
+            '"' => {
+                let current = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => current.push(c),
+                            Some(c) => {
+                                current.push('\\');
+                                current.push(c);
+                            },
+                            None => return Err(Error::Parse(format!("unterminated quote in {:?}", command))),
+                        },
+                        Some(c) => current.push(c),
+                        None => return Err(Error::Parse(format!("unterminated quote in {:?}", command))),
+                    }
+                }
+            },
+            '\\' => {
+                if let Some(c) = chars.next() {
+                    word.get_or_insert_with(String::new).push(c);
+                }
+            },
+            _ => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    words.extend(word);
+
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_export() {
+        let mut code = CodeContainer::new();
+        code.open_new_group();
+        code.push("(print \"check\")".into());
+        code.close_group();
+
+        let lang = Custom::new("fennel", "fennel");
+        let output = lang.export(code);
+        let expected_output = "#!/usr/bin/env fennel\n\n(print \"check\")".to_string();
+        assert_eq!(output, expected_output);
+    }
+
+    #[test]
+    fn test_export_without_shebang() {
+        let mut code = CodeContainer::new();
+        code.open_new_group();
+        code.push("(print \"check\")".into());
+        code.close_group();
+
+        let mut lang = Custom::new("fennel", "fennel");
+        lang.shebang = Some("".into());
+        let output = lang.export(code);
+        assert_eq!(output, "(print \"check\")");
+    }
+
+    #[test]
+    fn test_default_args() {
+        let lang = Custom::new("fennel", "fennel");
+        let args = lang.args(Some(Path::new("/tmp/script.fnl")), vec!["--my-flag".into(), "file".into()]);
+        let expected_args = vec!["/tmp/script.fnl", "--my-flag", "file"];
+        assert_eq!(args, expected_args);
+    }
+
+    #[test]
+    fn test_template_args() {
+        let mut lang = Custom::new("jq", "jq");
+        lang.args = vec!["-n".into(), "--from-file={script}".into(), "--args".into(), "{args}".into()];
+
+        let args = lang.args(Some(Path::new("/tmp/script.jq")), vec!["a".into(), "b".into()]);
+        let expected_args = vec!["-n", "--from-file=/tmp/script.jq", "--args", "a", "b"];
+        assert_eq!(args, expected_args);
+
+        let args = lang.args(None, vec![]);
+        let expected_args = vec!["-n", "--from-file=-", "--args"];
+        assert_eq!(args, expected_args);
+    }
+
+    #[test]
+    fn test_split_command() {
+        let test_cases: Vec<(&str, Option<Vec<&str>>)> = vec![
+            ("jq -f {file}", Some(vec!["jq", "-f", "{file}"])),
+            ("  sqlite3   db.sqlite ", Some(vec!["sqlite3", "db.sqlite"])),
+            ("awk 'BEGIN { print 1 }'", Some(vec!["awk", "BEGIN { print 1 }"])),
+            (r#"psql -c "select \"a\"""#, Some(vec!["psql", "-c", "select \"a\""])),
+            (r"my\ tool ''", Some(vec!["my tool", ""])),
+            ("", Some(vec![])),
+            ("jq 'unterminated", None),
+        ];
+
+        for case in test_cases {
+            let result = split_command(case.0).ok();
+            assert_eq!(result, case.1.map(|w| w.into_iter().map(String::from).collect()));
+        }
+    }
+
+    #[test]
+    fn test_from_command() {
+        let lang = Custom::from_command("jq", "jq -f {file}").unwrap();
+        assert_eq!(lang.binary(), "jq");
+        assert_eq!(lang.input, None);
+        assert_eq!(lang.extension(), ".jq");
+        let args = lang.args(Some(Path::new("/tmp/script.jq")), vec!["data.json".into()]);
+        assert_eq!(args, vec!["-f", "/tmp/script.jq", "data.json"]);
+
+        let lang = Custom::from_command("sql", "sqlite3 db.sqlite").unwrap();
+        assert_eq!(lang.input, Some(Input::Stdin));
+        let args = lang.args(None, vec![]);
+        assert_eq!(args, vec!["db.sqlite"]);
+
+        let lang = Custom::from_command("all", "gnuplot {args} {script}").unwrap();
+        let args = lang.args(Some(Path::new("/tmp/plot")), vec!["-p".into()]);
+        assert_eq!(args, vec!["-p", "/tmp/plot"]);
+
+        assert!(Custom::from_command("sql", " ").is_err());
+    }
+
+    #[test]
+    fn test_binary() {
+        let lang = Custom::new("fennel", "/opt/fennel/bin/fennel");
+        assert_eq!(lang.binary(), "/opt/fennel/bin/fennel")
+    }
+}
@@ -0,0 +1,81 @@
+use std::path::Path;
+
+use crate::code_container::CodeContainer;
+
+use super::{script_arg, Executor};
+
+pub struct Elixir;
+
+impl Executor for Elixir {
+    fn export(&self, script: CodeContainer) -> String {
+        let mut header: Vec<String> = vec![
+            "#!/usr/bin/env elixir".into(),
+            "".into(),
+        ];
+
+        header.push(script.lines());
+
+        header.join("\n")
+    }
+
+    fn binary(&self) -> &'static str {
+        "elixir"
+    }
+
+    fn args(&self, script: Option<&Path>, args: Vec<String>) -> Vec<String> {
+        // elixir has no option to read the script from stdin.
+        let mut argv = vec![script_arg(script, "/dev/stdin")];
+        argv.extend(args);
+        argv
+    }
+
+    fn extension(&self) -> &'static str {
+        ".exs"
+    }
+}
+
+impl Elixir {
+    pub fn new() -> Elixir {
+        Elixir{}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export() {
+        let mut code = CodeContainer::new();
+        code.open_new_group();
+        code.push("IO.puts(\"check\")".into());
+        code.close_group();
+
+        let lang = Elixir::new();
+        let output = lang.export(code);
+        let expected_output = "#!/usr/bin/env elixir\n\nIO.puts(\"check\")".to_string();
+        assert_eq!(output, expected_output);
+    }
+
+    #[test]
+    fn test_args() {
+        let lang = Elixir::new();
+        let args = lang.args(None, vec!["--my-flag".into(), "-o".into(), "file".into()]);
+        let expected_args = vec!["/dev/stdin", "--my-flag", "-o", "file"];
+        assert_eq!(args, expected_args);
+    }
+
+    #[test]
+    fn test_file_args() {
+        let lang = Elixir::new();
+        let args = lang.args(Some(Path::new("/tmp/script.exs")), vec!["--my-flag".into()]);
+        let expected_args = vec!["/tmp/script.exs", "--my-flag"];
+        assert_eq!(args, expected_args);
+    }
+
+    #[test]
+    fn test_elixir_binary() {
+        let lang = Elixir::new();
+        assert_eq!(lang.binary(), "elixir")
+    }
+}
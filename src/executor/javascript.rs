@@ -1,36 +1,72 @@
-use std::process::{Command, Stdio};
-use std::io::Write;
+use std::path::Path;
 
+use crate::code_block_options::CB_OPTION_MODULE;
 use crate::code_container::CodeContainer;
+use crate::error::Error;
 
-use super::Executor;
+use super::{process, script_arg, Context, Driver, Executor, Process};
 
-#[derive(Default)]
-pub enum JavaScript {
+/// Fence tags of TypeScript code blocks.
+const TYPESCRIPT_TAGS: &[&str] = &["ts", "typescript", "mts", "cts"];
+
+/// Evaluates the blocks in the global context of node, like the REPL.
+const SESSION_DRIVER: &str = r#"
+const fs = require("fs");
+const vm = require("vm");
+globalThis.require = require;
+const lines = require("readline").createInterface({ input: fs.createReadStream(null, { fd: {fd} }) });
+lines.on("line", (path) => {
+  if (!path) return lines.close();
+  let status = 0;
+  try {
+    vm.runInThisContext(fs.readFileSync(path, "utf8"), { filename: path });
+  } catch (err) {
+    console.error(err);
+    status = 1;
+  }
+  process.stdout.write(`{sentinel} ${status}
+`);
+});
+"#;
+
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub enum Runtime {
+    Bun,
     Deno,
     #[default]
     Node,
+    Tsx,
 }
 
-impl Executor for JavaScript {
-    fn exec(&self, script: CodeContainer, argv: Vec<String>) -> std::process::Child {
-        let mut prog = Command::new(self.target_str())
-            .args(self.args(argv))
-            .stdin(Stdio::piped())
-            .spawn()
-            .unwrap();
+/// Module system for node, selected with the "module" option on the code
+/// blocks.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Module {
+    CommonJs,
+    Esm,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct JavaScript {
+    runtime: Runtime,
+    typescript: bool,
+    module: Option<Module>,
+}
 
-        let mut stdin = prog.stdin.take().expect("Failed to open stdin");
-        std::thread::spawn(move || {
-            stdin.write_all(script.lines().as_bytes()).expect("Failed to write to stdin");
-        });
+impl Executor for JavaScript {
+    fn exec(&self, script: CodeContainer, argv: Vec<String>, ctx: &Context) -> Result<Process, Error> {
+        let js = self.for_script(&script)?;
 
-        prog
+        process::spawn(&js, script.lines(), argv, ctx)
     }
 
     fn export(&self, script: CodeContainer) -> String {
+        let interpreter = match (self.runtime, self.typescript) {
+            (Runtime::Node, true) => "-S node --experimental-strip-types",
+            _ => self.target_str(),
+        };
         let mut header: Vec<String> = vec![
-            format!("#!/usr/bin/env {}", self.target_str()),
+            format!("#!/usr/bin/env {}", interpreter),
             "".into(),
         ];
 
@@ -42,37 +78,116 @@ impl Executor for JavaScript {
     fn binary(&self) -> &'static str {
         self.target_str()
     }
-}
 
-impl JavaScript {
-    pub fn new(runner: &str) -> JavaScript {
-        match runner {
-            "node" => JavaScript::Node,
-            "deno" => JavaScript::Deno,
-            _ => JavaScript::default(),
+    fn args(&self, script: Option<&Path>, args: Vec<String>) -> Vec<String> {
+        let mut argv: Vec<String> = match self.runtime {
+            Runtime::Bun | Runtime::Deno => vec!["run".into()],
+            Runtime::Node | Runtime::Tsx => vec![],
+        };
+
+        match (self.runtime, script) {
+            (Runtime::Node, _) if self.typescript => argv.push("--experimental-strip-types".into()),
+            (Runtime::Deno, None) if self.typescript => argv.push("--ext=ts".into()),
+            _ => {},
         }
+
+        // From stdin, node can't use the file extension to decide the module
+        // system.
+        if let (Runtime::Node, None) = (self.runtime, script) {
+            let input_type = match (self.module, self.typescript) {
+                (Some(Module::CommonJs), false) => Some("commonjs"),
+                (Some(Module::Esm), false) => Some("module"),
+                (Some(Module::CommonJs), true) => Some("commonjs-typescript"),
+                (_, true) => Some("module-typescript"),
+                (None, false) => None,
+            };
+            if let Some(input_type) = input_type {
+                argv.push(format!("--input-type={}", input_type));
+            }
+        }
+
+        argv.push(script_arg(script, "-"));
+        argv.extend(args);
+        argv
     }
 
-    fn target_str(&self) -> &'static str {
-        match self {
-            JavaScript::Deno => "deno",
-            JavaScript::Node => "node",
+    fn extension(&self) -> &'static str {
+        match (self.module, self.typescript) {
+            (None, false) => ".js",
+            (Some(Module::Esm), false) => ".mjs",
+            (Some(Module::CommonJs), false) => ".cjs",
+            (None, true) => ".ts",
+            (Some(Module::Esm), true) => ".mts",
+            (Some(Module::CommonJs), true) => ".cts",
         }
     }
 
-    fn args(&self, args: Vec<String>) -> Vec<String> {
-        let mut argv = match self {
-            JavaScript::Deno => vec!["run".to_string(), "-".to_string()],
-            JavaScript::Node => vec!["-".to_string()],
+    fn session_args(&self, driver: &Driver, argv: Vec<String>) -> Option<Vec<String>> {
+        if self.runtime != Runtime::Node || self.typescript {
+            return None
+        }
+
+        let mut args = vec!["-e".to_string(), driver.render(SESSION_DRIVER)];
+        args.extend(argv);
+        Some(args)
+    }
+}
+
+impl JavaScript {
+    pub fn new(runner: &str) -> Result<JavaScript, Error> {
+        let runtime = match runner {
+            "node" => Runtime::Node,
+            "deno" => Runtime::Deno,
+            "bun" => Runtime::Bun,
+            _ => return Err(Error::UnknownLanguage(format!("javascript:{}", runner))),
         };
-        argv.extend(args);
-        argv
+
+        Ok(JavaScript { runtime, ..JavaScript::default() })
+    }
+
+    pub fn typescript(runner: &str) -> Result<JavaScript, Error> {
+        let runtime = match runner {
+            "tsx" => Runtime::Tsx,
+            _ => JavaScript::new(runner)
+                .map_err(|_| Error::UnknownLanguage(format!("typescript:{}", runner)))?
+                .runtime,
+        };
+
+        Ok(JavaScript { runtime, typescript: true, module: None })
+    }
+
+    /// Settings for the script: code blocks tagged as TypeScript are
+    /// evaluated as TypeScript with any runtime (`ts:deno`), and the module
+    /// system can be selected with the "module" option (esm or cjs).
+    fn for_script(&self, script: &CodeContainer) -> Result<JavaScript, Error> {
+        let blocks = script.blocks();
+        let typescript = self.typescript
+            || (!blocks.is_empty() && blocks.iter().all(|b| TYPESCRIPT_TAGS.contains(&b.tag.as_str())));
+
+        let module = match blocks.iter().find_map(|b| b.option(CB_OPTION_MODULE)).as_deref() {
+            None => self.module,
+            Some("esm") | Some("module") => Some(Module::Esm),
+            Some("cjs") | Some("commonjs") => Some(Module::CommonJs),
+            Some(other) => return Err(Error::Parse(format!("invalid module {:?}, expected esm or cjs", other))),
+        };
+
+        Ok(JavaScript { runtime: self.runtime, typescript, module })
+    }
+
+    fn target_str(&self) -> &'static str {
+        match self.runtime {
+            Runtime::Bun => "bun",
+            Runtime::Deno => "deno",
+            Runtime::Node => "node",
+            Runtime::Tsx => "tsx",
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::code_block_options::CodeBlockOption;
 
     #[test]
     fn test_default_export() {
@@ -90,7 +205,7 @@ mod tests {
     #[test]
     fn test_default_args() {
         let lang = JavaScript::default();
-        let args = lang.args(vec!["--my-flag".into(), "-o".into(), "file".into()]);
+        let args = lang.args(None, vec!["--my-flag".into(), "-o".into(), "file".into()]);
         let expected_args = vec!["-", "--my-flag", "-o", "file"];
         assert_eq!(args, expected_args);
     }
@@ -102,7 +217,7 @@ mod tests {
         code.push("console.log(\"check\")".into());
         code.close_group();
 
-        let lang = JavaScript::new("node");
+        let lang = JavaScript::new("node").unwrap();
         let output = lang.export(code);
         let expected_output = "#!/usr/bin/env node\n\nconsole.log(\"check\")".to_string();
         assert_eq!(output, expected_output);
@@ -110,8 +225,8 @@ mod tests {
 
     #[test]
     fn test_node_args() {
-        let lang = JavaScript::new("node");
-        let args = lang.args(vec!["--my-flag".into(), "-o".into(), "file".into()]);
+        let lang = JavaScript::new("node").unwrap();
+        let args = lang.args(None, vec!["--my-flag".into(), "-o".into(), "file".into()]);
         let expected_args = vec!["-", "--my-flag", "-o", "file"];
         assert_eq!(args, expected_args);
     }
@@ -123,7 +238,7 @@ mod tests {
         code.push("console.log(\"check\")".into());
         code.close_group();
 
-        let lang = JavaScript::new("deno");
+        let lang = JavaScript::new("deno").unwrap();
         let output = lang.export(code);
         let expected_output = "#!/usr/bin/env deno\n\nconsole.log(\"check\")".to_string();
         assert_eq!(output, expected_output);
@@ -131,21 +246,127 @@ mod tests {
 
     #[test]
     fn test_deno_args() {
-        let lang = JavaScript::new("deno");
-        let args = lang.args(vec!["--my-flag".into(), "-o".into(), "file".into()]);
+        let lang = JavaScript::new("deno").unwrap();
+        let args = lang.args(None, vec!["--my-flag".into(), "-o".into(), "file".into()]);
         let expected_args = vec!["run", "-", "--my-flag", "-o", "file"];
         assert_eq!(args, expected_args);
     }
 
+    #[test]
+    fn test_node_file_args() {
+        let lang = JavaScript::new("node").unwrap();
+        let args = lang.args(Some(Path::new("/tmp/script.js")), vec!["--my-flag".into()]);
+        let expected_args = vec!["/tmp/script.js", "--my-flag"];
+        assert_eq!(args, expected_args);
+    }
+
+    #[test]
+    fn test_deno_file_args() {
+        let lang = JavaScript::new("deno").unwrap();
+        let args = lang.args(Some(Path::new("/tmp/script.js")), vec!["--my-flag".into()]);
+        let expected_args = vec!["run", "/tmp/script.js", "--my-flag"];
+        assert_eq!(args, expected_args);
+    }
+
+    #[test]
+    fn test_bun_args() {
+        let lang = JavaScript::new("bun").unwrap();
+        let args = lang.args(Some(Path::new("/tmp/script.js")), vec!["--my-flag".into()]);
+        let expected_args = vec!["run", "/tmp/script.js", "--my-flag"];
+        assert_eq!(args, expected_args);
+    }
+
+    #[test]
+    fn test_unknown_runtime() {
+        assert!(matches!(JavaScript::new("nodee"), Err(Error::UnknownLanguage(_))));
+        assert!(matches!(JavaScript::typescript("nodee"), Err(Error::UnknownLanguage(_))));
+    }
+
+    #[test]
+    fn test_typescript_args() {
+        let file = Some(Path::new("/tmp/script.ts"));
+        let test_cases: Vec<(&str, Option<&Path>, Vec<&str>)> = vec![
+            ("node", file, vec!["--experimental-strip-types", "/tmp/script.ts"]),
+            ("node", None, vec!["--experimental-strip-types", "--input-type=module-typescript", "-"]),
+            ("deno", file, vec!["run", "/tmp/script.ts"]),
+            ("deno", None, vec!["run", "--ext=ts", "-"]),
+            ("bun", file, vec!["run", "/tmp/script.ts"]),
+            ("tsx", file, vec!["/tmp/script.ts"]),
+        ];
+
+        for case in test_cases {
+            let lang = JavaScript::typescript(case.0).unwrap();
+            assert_eq!(lang.args(case.1, vec![]), case.2);
+        }
+    }
+
+    #[test]
+    fn test_typescript_export() {
+        let mut code = CodeContainer::new();
+        code.open_new_group();
+        code.push("console.log(\"check\")".into());
+        code.close_group();
+
+        let lang = JavaScript::typescript("node").unwrap();
+        let output = lang.export(code);
+        let expected_output = "#!/usr/bin/env -S node --experimental-strip-types\n\nconsole.log(\"check\")".to_string();
+        assert_eq!(output, expected_output);
+    }
+
+    #[test]
+    fn test_for_script() {
+        let mut code = CodeContainer::new();
+        code.open_block(1, "ts", CodeBlockOption::parse_options("```ts #module=cjs"));
+        code.push("const a: number = 1".into());
+        code.close_group();
+
+        let lang = JavaScript::new("deno").unwrap().for_script(&code).unwrap();
+        assert_eq!(lang.runtime, Runtime::Deno);
+        assert!(lang.typescript);
+        assert_eq!(lang.module, Some(Module::CommonJs));
+        assert_eq!(lang.extension(), ".cts");
+
+        code.open_block(5, "js", CodeBlockOption::parse_options("```js"));
+        code.push("const b = 1".into());
+        code.close_group();
+
+        let lang = JavaScript::new("node").unwrap().for_script(&code).unwrap();
+        assert!(!lang.typescript);
+        assert_eq!(lang.extension(), ".cjs");
+        assert_eq!(lang.args(None, vec![]), vec!["--input-type=commonjs", "-"]);
+    }
+
+    #[test]
+    fn test_for_script_invalid_module() {
+        let mut code = CodeContainer::new();
+        code.open_block(1, "js", CodeBlockOption::parse_options("```js #module=amd"));
+        code.push("define([], () => {})".into());
+        code.close_group();
+
+        assert!(JavaScript::new("node").unwrap().for_script(&code).is_err());
+    }
+
     #[test]
     fn test_deno_binary() {
-        let lang = JavaScript::new("deno");
+        let lang = JavaScript::new("deno").unwrap();
         assert_eq!(lang.binary(), "deno")
     }
 
     #[test]
     fn test_node_binary() {
-        let lang = JavaScript::new("node");
+        let lang = JavaScript::new("node").unwrap();
         assert_eq!(lang.binary(), "node")
     }
+
+    #[test]
+    fn test_session_args() {
+        let driver = Driver { sentinel: "SENTINEL".into(), fd: 3 };
+        let args = JavaScript::new("node").unwrap().session_args(&driver, vec!["a".into()]).unwrap();
+        assert_eq!(args[0], "-e");
+        assert!(args[1].contains("SENTINEL"));
+        assert_eq!(args[2], "a");
+
+        assert_eq!(JavaScript::new("deno").unwrap().session_args(&driver, vec![]), None);
+        assert_eq!(JavaScript::typescript("node").unwrap().session_args(&driver, vec![]), None);
+    }
 }
@@ -0,0 +1,168 @@
+use std::process::{Child, Command};
+
+use super::Context;
+
+/// A script started in its own process group. On a terminal the group gets
+/// the foreground, so the script can read from it and Ctrl-C reaches it.
+/// SIGINT and SIGTERM sent to eval-md are forwarded to the group. The terminal
+/// is taken back when the job is dropped.
+pub struct Job {
+    #[cfg(unix)]
+    slot: Option<usize>,
+    #[cfg(unix)]
+    foreground: bool,
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::sync::{atomic::{AtomicI32, Ordering}, Once};
+
+    /// Process groups that receive the forwarded signals, 0 is a free slot.
+    pub static GROUPS: [AtomicI32; 4] = [AtomicI32::new(0), AtomicI32::new(0), AtomicI32::new(0), AtomicI32::new(0)];
+
+    static HANDLERS: Once = Once::new();
+
+    pub extern "C" fn forward(signal: libc::c_int) {
+        let mut forwarded = false;
+        for group in &GROUPS {
+            let group = group.load(Ordering::SeqCst);
+            if group > 0 {
+                unsafe { libc::kill(-group, signal) };
+                forwarded = true;
+            }
+        }
+
+        // Without a running script the signal does what it would do anyway.
+        if !forwarded {
+            unsafe {
+                libc::signal(signal, libc::SIG_DFL);
+                libc::raise(signal);
+            }
+        }
+    }
+
+    pub fn install_handlers() {
+        HANDLERS.call_once(|| unsafe {
+            let handler = forward as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            libc::signal(libc::SIGINT, handler);
+            libc::signal(libc::SIGTERM, handler);
+        });
+    }
+
+    pub fn register(group: i32) -> Option<usize> {
+        GROUPS.iter().position(|slot| slot.compare_exchange(0, group, Ordering::SeqCst, Ordering::SeqCst).is_ok())
+    }
+
+    /// Runs the function with the controlling terminal, stdin of the script
+    /// can be something else.
+    fn with_terminal<T>(f: impl FnOnce(libc::c_int) -> T) -> Option<T> {
+        unsafe {
+            let tty = libc::open(c"/dev/tty".as_ptr(), libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC);
+            if tty < 0 {
+                return None
+            }
+            let result = f(tty);
+            libc::close(tty);
+
+            Some(result)
+        }
+    }
+
+    /// Foreground process group of the controlling terminal.
+    pub fn foreground_group() -> Option<libc::pid_t> {
+        with_terminal(|tty| unsafe { libc::tcgetpgrp(tty) })
+    }
+
+    /// The process group of eval-md is the foreground one, so it can hand the
+    /// terminal over.
+    pub fn is_foreground() -> bool {
+        foreground_group() == Some(unsafe { libc::getpgrp() })
+    }
+
+    /// Makes the process group of the calling process the foreground one. A
+    /// background process gets SIGTTOU for this, so it's ignored meanwhile.
+    pub fn take_terminal() {
+        with_terminal(|tty| unsafe {
+            let previous = libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+            libc::tcsetpgrp(tty, libc::getpgrp());
+            libc::signal(libc::SIGTTOU, previous);
+        });
+    }
+}
+
+/// Starts the command in its own process group, which gets the terminal if
+/// eval-md has it.
+#[cfg(unix)]
+pub fn prepare(command: &mut Command) {
+    use std::os::unix::process::CommandExt;
+
+    command.process_group(0);
+    if unix::is_foreground() {
+        // Runs in the child after it moved into its new group.
+        unsafe {
+            command.pre_exec(|| {
+                unix::take_terminal();
+                Ok(())
+            });
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn prepare(_command: &mut Command) {}
+
+impl Job {
+    /// None if the script is not in its own process group.
+    #[cfg(unix)]
+    pub fn new(child: &Child, ctx: &Context) -> Option<Job> {
+        if !ctx.process_group {
+            return None
+        }
+
+        // The child may have taken the terminal already.
+        let group = child.id() as libc::pid_t;
+        let foreground = unix::is_foreground() || unix::foreground_group() == Some(group);
+
+        unix::install_handlers();
+        Some(Job { slot: unix::register(group), foreground })
+    }
+
+    #[cfg(not(unix))]
+    pub fn new(_child: &Child, _ctx: &Context) -> Option<Job> {
+        None
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Job {
+    fn drop(&mut self) {
+        if let Some(slot) = self.slot {
+            unix::GROUPS[slot].store(0, std::sync::atomic::Ordering::SeqCst);
+        }
+        if self.foreground {
+            unix::take_terminal();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(unix)]
+    #[test]
+    fn test_forward() {
+        use std::os::unix::process::{CommandExt, ExitStatusExt};
+        use std::process::Command;
+        use std::sync::atomic::Ordering;
+
+        use super::unix;
+
+        let mut child = Command::new("sh").args(["-c", "sleep 10"]).process_group(0).spawn().unwrap();
+        let slot = unix::register(child.id() as i32).unwrap();
+
+        unix::forward(libc::SIGTERM);
+        let status = child.wait().unwrap();
+        unix::GROUPS[slot].store(0, Ordering::SeqCst);
+
+        assert_eq!(status.signal(), Some(libc::SIGTERM));
+    }
+}
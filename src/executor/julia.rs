@@ -0,0 +1,80 @@
+use std::path::Path;
+
+use crate::code_container::CodeContainer;
+
+use super::{script_arg, Executor};
+
+pub struct Julia;
+
+impl Executor for Julia {
+    fn export(&self, script: CodeContainer) -> String {
+        let mut header: Vec<String> = vec![
+            "#!/usr/bin/env julia".into(),
+            "".into(),
+        ];
+
+        header.push(script.lines());
+
+        header.join("\n")
+    }
+
+    fn binary(&self) -> &'static str {
+        "julia"
+    }
+
+    fn args(&self, script: Option<&Path>, args: Vec<String>) -> Vec<String> {
+        let mut argv = vec![script_arg(script, "-")];
+        argv.extend(args);
+        argv
+    }
+
+    fn extension(&self) -> &'static str {
+        ".jl"
+    }
+}
+
+impl Julia {
+    pub fn new() -> Julia {
+        Julia{}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export() {
+        let mut code = CodeContainer::new();
+        code.open_new_group();
+        code.push("println(\"check\")".into());
+        code.close_group();
+
+        let lang = Julia::new();
+        let output = lang.export(code);
+        let expected_output = "#!/usr/bin/env julia\n\nprintln(\"check\")".to_string();
+        assert_eq!(output, expected_output);
+    }
+
+    #[test]
+    fn test_args() {
+        let lang = Julia::new();
+        let args = lang.args(None, vec!["--my-flag".into(), "-o".into(), "file".into()]);
+        let expected_args = vec!["-", "--my-flag", "-o", "file"];
+        assert_eq!(args, expected_args);
+    }
+
+    #[test]
+    fn test_file_args() {
+        let lang = Julia::new();
+        let args = lang.args(Some(Path::new("/tmp/script.jl")), vec!["--my-flag".into()]);
+        let expected_args = vec!["/tmp/script.jl", "--my-flag"];
+        assert_eq!(args, expected_args);
+    }
+
+    #[test]
+    fn test_julia_binary() {
+        let lang = Julia::new();
+        assert_eq!(lang.binary(), "julia")
+    }
+}
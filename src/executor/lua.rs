@@ -1,31 +1,17 @@
-use std::process::{Command, Stdio};
-use std::io::Write;
+use std::path::Path;
 
 use crate::code_container::CodeContainer;
 
-use super::Executor;
+use super::{script_arg, shebang, Executor};
 
-pub struct Lua;
+pub struct Lua {
+    binary: String,
+}
 
 impl Executor for Lua {
-    fn exec(&self, script: CodeContainer, argv: Vec<String>) -> std::process::Child {
-        let mut prog = Command::new("lua")
-            .args(self.args(argv))
-            .stdin(Stdio::piped())
-            .spawn()
-            .unwrap();
-
-        let mut stdin = prog.stdin.take().expect("Failed to open stdin");
-        std::thread::spawn(move || {
-            stdin.write_all(script.lines().as_bytes()).expect("Failed to write to stdin");
-        });
-
-        prog
-    }
-
     fn export(&self, script: CodeContainer) -> String {
         let mut header: Vec<String> = vec![
-            "#!/usr/bin/env lua".into(),
+            shebang(&self.binary),
             "".into(),
         ];
 
@@ -34,20 +20,30 @@ impl Executor for Lua {
         header.join("\n")
     }
 
-    fn binary(&self) -> &'static str {
-        "lua"
+    fn binary(&self) -> &str {
+        &self.binary
+    }
+
+    fn args(&self, script: Option<&Path>, args: Vec<String>) -> Vec<String> {
+        let mut argv = vec![script_arg(script, "-")];
+        argv.extend(args);
+        argv
+    }
+
+    fn extension(&self) -> &'static str {
+        ".lua"
     }
 }
 
 impl Lua {
     pub fn new() -> Lua {
-        Lua{}
+        Lua::with_binary("lua")
     }
 
-    fn args(&self, args: Vec<String>) -> Vec<String> {
-        let mut argv = vec!["-".to_string()];
-        argv.extend(args);
-        argv
+    /// Interpreter with a custom binary name or path, for example a specific
+    /// version.
+    pub fn with_binary(binary: &str) -> Lua {
+        Lua { binary: binary.to_string() }
     }
 }
 
@@ -71,14 +67,37 @@ mod tests {
     #[test]
     fn test_args() {
         let lang = Lua::new();
-        let args = lang.args(vec!["--my-flag".into(), "-o".into(), "file".into()]);
+        let args = lang.args(None, vec!["--my-flag".into(), "-o".into(), "file".into()]);
         let expected_args = vec!["-", "--my-flag", "-o", "file"];
         assert_eq!(args, expected_args);
     }
 
+    #[test]
+    fn test_file_args() {
+        let lang = Lua::new();
+        let script = format!("/tmp/script{}", lang.extension());
+        let args = lang.args(Some(Path::new(&script)), vec!["--my-flag".into()]);
+        let expected_args = vec![script.as_str(), "--my-flag"];
+        assert_eq!(args, expected_args);
+    }
+
     #[test]
     fn test_lua_binary() {
         let lang = Lua::new();
         assert_eq!(lang.binary(), "lua")
     }
+
+    #[test]
+    fn test_custom_binary() {
+        let mut code = CodeContainer::new();
+        code.open_new_group();
+        code.push("print(\"check\")".into());
+        code.close_group();
+
+        let lang = Lua::with_binary("luajit");
+        assert_eq!(lang.binary(), "luajit");
+        let output = lang.export(code);
+        let expected_output = "#!/usr/bin/env luajit\n\nprint(\"check\")".to_string();
+        assert_eq!(output, expected_output);
+    }
 }
@@ -1,28 +1,101 @@
-use std::process::Child;
+use std::path::Path;
 
+mod awk;
+mod compiled;
+mod context;
+mod custom;
+mod elixir;
+mod job;
 mod javascript;
+mod julia;
 mod lua;
+mod perl;
 mod php;
+mod process;
 mod python;
+mod r;
+mod registry;
 mod ruby;
+mod session;
 mod shell;
+mod sql;
+mod tcl;
 
+pub use awk::Awk;
+pub use compiled::{hash, Compiled};
+pub use context::{Context, Input};
+pub use custom::Custom;
+pub use elixir::Elixir;
+pub use job::Job;
 pub use javascript::JavaScript;
+pub use julia::Julia;
 pub use lua::Lua;
+pub use perl::Perl;
 pub use php::Php;
+pub use process::Process;
 pub use python::Python;
+pub use r::R;
+pub use registry::Registry;
 pub use ruby::Ruby;
+pub use session::{Driver, Outcome, Session};
 pub use shell::Shell;
+pub use sql::Sql;
+pub use tcl::Tcl;
 
 use crate::code_container::CodeContainer;
+use crate::comment::Comment;
+use crate::error::Error;
 
 pub trait Executor {
-    fn exec(&self, script: CodeContainer, argv: Vec<String>) -> Child;
+    fn exec(&self, script: CodeContainer, argv: Vec<String>, ctx: &Context) -> Result<Process, Error> {
+        process::spawn(self, script.lines(), argv, ctx)
+    }
     fn export(&self, script: CodeContainer) -> String;
-    fn binary(&self) -> &'static str;
+    fn binary(&self) -> &str;
+    /// Arguments of the interpreter. Without a script path, the interpreter
+    /// has to read the script from stdin.
+    fn args(&self, script: Option<&Path>, argv: Vec<String>) -> Vec<String>;
+    /// Extension of the temporary script file.
+    fn extension(&self) -> &str;
+    /// Comment syntax of the language for `--annotate`, None if comments
+    /// can't be added to the script. The syntax of the file extension by
+    /// default.
+    fn comment(&self) -> Option<Comment> {
+        Comment::for_tag(self.extension().trim_start_matches('.'))
+    }
+
+    /// Starts a persistent interpreter for `--session`.
+    fn session(&self, argv: Vec<String>, ctx: &Context) -> Result<Session, Error> {
+        session::start(self, argv, ctx)
+    }
+    /// Arguments of the interpreter to run a session driver that reports the
+    /// end of each block with the sentinel, see [`Session`]. `None` if the
+    /// language doesn't support sessions.
+    fn session_args(&self, _driver: &Driver, _argv: Vec<String>) -> Option<Vec<String>> {
+        None
+    }
+}
+
+/// Script path argument, or the one that makes the interpreter read the
+/// script from stdin.
+fn script_arg(script: Option<&Path>, stdin: &str) -> String {
+    match script {
+        Some(path) => path.to_string_lossy().to_string(),
+        None => stdin.to_string(),
+    }
+}
+
+/// Shebang line of an exported script, interpreters with an absolute path
+/// are used directly.
+fn shebang(binary: &str) -> String {
+    if Path::new(binary).is_absolute() {
+        format!("#!{}", binary)
+    } else {
+        format!("#!/usr/bin/env {}", binary)
+    }
 }
 
-pub fn language_picker(executor: &str) -> Option<Box<dyn Executor>> {
+pub fn language_picker(executor: &str) -> Result<Box<dyn Executor>, Error> {
     let (lang, executor) = if let Some((l, e)) = resolve_alias(executor) {
         (l, Some(e))
     } else {
@@ -30,38 +103,76 @@ pub fn language_picker(executor: &str) -> Option<Box<dyn Executor>> {
     };
 
     match lang {
+        "awk" => Ok(Box::new(Awk::new())),
+        "c" => Ok(Box::new(Compiled::C)),
+        "cpp" => Ok(Box::new(Compiled::Cpp)),
+        "elixir" => Ok(Box::new(Elixir::new())),
+        "go" => Ok(Box::new(Compiled::Go)),
         "javascript" => {
             let js = if let Some(executor) = executor {
-                JavaScript::new(executor)
+                JavaScript::new(executor)?
             } else {
                 JavaScript::default()
             };
-            Some(Box::new(js))
+            Ok(Box::new(js))
         },
-        "lua" => Some(Box::new(Lua::new())),
-        "python" => Some(Box::new(Python::new())),
-        "ruby" => Some(Box::new(Ruby::new())),
-        "php" => Some(Box::new(Php::new())),
+        "typescript" => Ok(Box::new(JavaScript::typescript(executor.unwrap_or("node"))?)),
+        "julia" => Ok(Box::new(Julia::new())),
+        "lua" => Ok(Box::new(Lua::new())),
+        "perl" => Ok(Box::new(Perl::new())),
+        "python" => Ok(Box::new(Python::new())),
+        "r" => Ok(Box::new(R::new())),
+        "ruby" => Ok(Box::new(Ruby::new())),
+        "rust" => Ok(Box::new(Compiled::Rust)),
+        "php" => Ok(Box::new(Php::new())),
+        "sql" => Ok(Box::new(Sql::new())),
+        "tcl" => Ok(Box::new(Tcl::new())),
         "shell" => {
             let sh = if let Some(executor) = executor {
-                Shell::new(executor)
+                Shell::new(executor)?
             } else {
                 Shell::default()
             };
-            Some(Box::new(sh))
+            Ok(Box::new(sh))
         },
-        _ => None
+        _ => Err(Error::UnknownLanguage(lang.to_string())),
+    }
+}
+
+/// Executor of the language of a fence tag with a custom interpreter, for
+/// example `py:python3.12` or `lua:/opt/luajit/bin/luajit`.
+pub fn custom_binary(tag: &str, binary: &str) -> Result<Box<dyn Executor>, Error> {
+    let lang = resolve_alias(tag).map(|(lang, _)| lang).unwrap_or(tag);
+
+    match lang {
+        "lua" => Ok(Box::new(Lua::with_binary(binary))),
+        "php" => Ok(Box::new(Php::with_binary(binary))),
+        "python" => Ok(Box::new(Python::with_binary(binary))),
+        "ruby" => Ok(Box::new(Ruby::with_binary(binary))),
+        _ => Err(Error::UnknownLanguage(binary.to_string())),
     }
 }
 
 pub fn supported_languages() -> Vec<&'static str> {
     vec![
+        "awk",
+        "c",
+        "cpp",
+        "elixir",
+        "go",
         "javascript",
+        "julia",
         "lua",
+        "perl",
         "php",
         "python",
+        "r",
         "ruby",
+        "rust",
         "shell",
+        "sql",
+        "tcl",
+        "typescript",
     ]
 }
 
@@ -69,9 +180,31 @@ pub fn aliases() -> Vec<(&'static str, &'static str, &'static str)> {
     vec![
         ("bash", "shell", "bash"),
         ("zsh", "shell", "zsh"),
+        ("sh", "shell", "sh"),
+        ("dash", "shell", "dash"),
+        ("ksh", "shell", "ksh"),
+        ("fish", "shell", "fish"),
+        ("pwsh", "shell", "pwsh"),
+        ("powershell", "shell", "pwsh"),
         ("js", "javascript", "node"),
         ("node", "javascript", "node"),
         ("deno", "javascript", "deno"),
+        ("bun", "javascript", "bun"),
+        ("ts", "typescript", "node"),
+        ("tsx", "typescript", "tsx"),
+        ("rs", "rust", "rustc"),
+        ("golang", "go", "go"),
+        ("c++", "cpp", "c++"),
+        ("py", "python", "python3"),
+        ("rb", "ruby", "ruby"),
+        ("pl", "perl", "perl"),
+        ("R", "r", "Rscript"),
+        ("jl", "julia", "julia"),
+        ("ex", "elixir", "elixir"),
+        ("exs", "elixir", "elixir"),
+        ("tclsh", "tcl", "tclsh"),
+        ("sqlite", "sql", "sqlite3"),
+        ("sqlite3", "sql", "sqlite3"),
     ]
 }
 
@@ -85,6 +218,23 @@ pub fn resolve_alias(name: &str) -> Option<(&'static str, &'static str)> {
     None
 }
 
+/// Other executors of the same language, suggested when the interpreter binary
+/// is not available.
+pub fn alternatives(binary: &str) -> Vec<&'static str> {
+    let lang = aliases()
+        .into_iter()
+        .find(|(_, _, executor)| *executor == binary)
+        .map(|(_, lang, _)| lang);
+
+    let mut list: Vec<&'static str> = vec![];
+    for (_, l, executor) in aliases() {
+        if Some(l) == lang && executor != binary && !list.contains(&executor) {
+            list.push(executor);
+        }
+    }
+    list
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -110,20 +260,103 @@ mod tests {
             ("bash", Some("bash")),
             ("zsh", Some("zsh")),
             ("shell", Some("zsh")),
+            ("sh", Some("sh")),
+            ("fish", Some("fish")),
+            ("powershell", Some("pwsh")),
             ("deno", Some("deno")),
             ("javascript", Some("node")),
             ("js", Some("node")),
+            ("bun", Some("bun")),
+            ("ts", Some("node")),
+            ("typescript", Some("node")),
+            ("tsx", Some("tsx")),
+            ("rust", Some("rustc")),
+            ("rs", Some("rustc")),
+            ("go", Some("go")),
+            ("c", Some("cc")),
+            ("c++", Some("c++")),
+            ("perl", Some("perl")),
+            ("pl", Some("perl")),
+            ("r", Some("Rscript")),
+            ("R", Some("Rscript")),
+            ("julia", Some("julia")),
+            ("exs", Some("elixir")),
+            ("awk", Some("awk")),
+            ("tcl", Some("tclsh")),
+            ("sql", Some("sqlite3")),
+            ("sqlite", Some("sqlite3")),
             ("something", None),
         ];
 
         for case in test_cases {
             let result = language_picker(case.0);
             if case.1.is_none() {
-                assert!(result.is_none());
+                assert!(result.is_err());
                 continue
             }
 
-            assert_eq!(Some(result.unwrap().binary()), case.1);
+            assert_eq!(Some(result.ok().unwrap().binary()), case.1);
+        }
+    }
+
+    #[test]
+    fn test_comment() {
+        let test_cases: Vec<(&str, Option<&str>)> = vec![
+            ("python", Some("#")),
+            ("fish", Some("#")),
+            ("pwsh", Some("#")),
+            ("r", Some("#")),
+            ("exs", Some("#")),
+            ("js", Some("//")),
+            ("ts", Some("//")),
+            ("rust", Some("//")),
+            ("c++", Some("//")),
+            ("lua", Some("--")),
+            ("sql", Some("--")),
+            ("php", None),
+        ];
+
+        for case in test_cases {
+            let comment = language_picker(case.0).unwrap().comment();
+            assert_eq!(comment.map(|comment| comment.start), case.1.map(str::to_string), "{}", case.0);
+        }
+    }
+
+    #[test]
+    fn test_custom_binary() {
+        let test_cases: Vec<(&str, &str, Option<&str>)> = vec![
+            ("py", "python3.12", Some("python3.12")),
+            ("python", "/opt/venv/bin/python", Some("/opt/venv/bin/python")),
+            ("lua", "luajit", Some("luajit")),
+            ("php", "php8.2", Some("php8.2")),
+            ("rb", "jruby", Some("jruby")),
+            ("bash", "mksh", None),
+            ("something", "python3", None),
+        ];
+
+        for case in test_cases {
+            let result = custom_binary(case.0, case.1).ok();
+            assert_eq!(result.as_ref().map(|e| e.binary()), case.2);
+        }
+    }
+
+    #[test]
+    fn test_shebang() {
+        assert_eq!(shebang("python3"), "#!/usr/bin/env python3");
+        assert_eq!(shebang("/opt/venv/bin/python"), "#!/opt/venv/bin/python");
+    }
+
+    #[test]
+    fn test_alternatives() {
+        let test_cases: Vec<(&str, Vec<&str>)> = vec![
+            ("node", vec!["deno", "bun"]),
+            ("deno", vec!["node", "bun"]),
+            ("bash", vec!["zsh", "sh", "dash", "ksh", "fish", "pwsh"]),
+            ("python3", vec![]),
+        ];
+
+        for case in test_cases {
+            assert_eq!(alternatives(case.0), case.1);
         }
     }
 }
@@ -0,0 +1,80 @@
+use std::path::Path;
+
+use crate::code_container::CodeContainer;
+
+use super::{script_arg, Executor};
+
+pub struct Perl;
+
+impl Executor for Perl {
+    fn export(&self, script: CodeContainer) -> String {
+        let mut header: Vec<String> = vec![
+            "#!/usr/bin/env perl".into(),
+            "".into(),
+        ];
+
+        header.push(script.lines());
+
+        header.join("\n")
+    }
+
+    fn binary(&self) -> &'static str {
+        "perl"
+    }
+
+    fn args(&self, script: Option<&Path>, args: Vec<String>) -> Vec<String> {
+        let mut argv = vec![script_arg(script, "-")];
+        argv.extend(args);
+        argv
+    }
+
+    fn extension(&self) -> &'static str {
+        ".pl"
+    }
+}
+
+impl Perl {
+    pub fn new() -> Perl {
+        Perl{}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export() {
+        let mut code = CodeContainer::new();
+        code.open_new_group();
+        code.push("print \"check\\n\";".into());
+        code.close_group();
+
+        let lang = Perl::new();
+        let output = lang.export(code);
+        let expected_output = "#!/usr/bin/env perl\n\nprint \"check\\n\";".to_string();
+        assert_eq!(output, expected_output);
+    }
+
+    #[test]
+    fn test_args() {
+        let lang = Perl::new();
+        let args = lang.args(None, vec!["--my-flag".into(), "-o".into(), "file".into()]);
+        let expected_args = vec!["-", "--my-flag", "-o", "file"];
+        assert_eq!(args, expected_args);
+    }
+
+    #[test]
+    fn test_file_args() {
+        let lang = Perl::new();
+        let args = lang.args(Some(Path::new("/tmp/script.pl")), vec!["--my-flag".into()]);
+        let expected_args = vec!["/tmp/script.pl", "--my-flag"];
+        assert_eq!(args, expected_args);
+    }
+
+    #[test]
+    fn test_perl_binary() {
+        let lang = Perl::new();
+        assert_eq!(lang.binary(), "perl")
+    }
+}
@@ -1,31 +1,18 @@
-use std::process::{Command, Stdio};
-use std::io::Write;
+use std::path::Path;
 
 use crate::code_container::CodeContainer;
+use crate::comment::Comment;
 
-use super::Executor;
+use super::{shebang, Executor};
 
-pub struct Php;
+pub struct Php {
+    binary: String,
+}
 
 impl Executor for Php {
-    fn exec(&self, script: CodeContainer, argv: Vec<String>) -> std::process::Child {
-        let mut prog = Command::new("php")
-            .args(self.args(argv))
-            .stdin(Stdio::piped())
-            .spawn()
-            .unwrap();
-
-        let mut stdin = prog.stdin.take().expect("Failed to open stdin");
-        std::thread::spawn(move || {
-            stdin.write_all(script.lines().as_bytes()).expect("Failed to write to stdin");
-        });
-
-        prog
-    }
-
     fn export(&self, script: CodeContainer) -> String {
         let mut header: Vec<String> = vec![
-            "#!/usr/bin/env php".into(),
+            shebang(&self.binary),
             "".into(),
         ];
 
@@ -34,20 +21,41 @@ impl Executor for Php {
         header.join("\n")
     }
 
-    fn binary(&self) -> &'static str {
-        "php"
+    fn binary(&self) -> &str {
+        &self.binary
+    }
+
+    fn args(&self, script: Option<&Path>, args: Vec<String>) -> Vec<String> {
+        // Without a file, php reads the script from stdin, and everything
+        // after "--" goes to the script.
+        let mut argv = match script {
+            Some(path) => vec![path.to_string_lossy().to_string()],
+            None => vec!["--".to_string()],
+        };
+        argv.extend(args);
+        argv
+    }
+
+    fn extension(&self) -> &'static str {
+        ".php"
+    }
+
+    /// Text outside of `<?php` tags is printed, so there is no place for a
+    /// comment before a block.
+    fn comment(&self) -> Option<Comment> {
+        None
     }
 }
 
 impl Php {
     pub fn new() -> Php {
-        Php{}
+        Php::with_binary("php")
     }
 
-    fn args(&self, args: Vec<String>) -> Vec<String> {
-        let mut argv = vec!["--".to_string()];
-        argv.extend(args);
-        argv
+    /// Interpreter with a custom binary name or path, for example a specific
+    /// version.
+    pub fn with_binary(binary: &str) -> Php {
+        Php { binary: binary.to_string() }
     }
 }
 
@@ -71,14 +79,37 @@ mod tests {
     #[test]
     fn test_args() {
         let lang = Php::new();
-        let args = lang.args(vec!["--my-flag".into(), "-o".into(), "file".into()]);
+        let args = lang.args(None, vec!["--my-flag".into(), "-o".into(), "file".into()]);
         let expected_args = vec!["--", "--my-flag", "-o", "file"];
         assert_eq!(args, expected_args);
     }
 
+    #[test]
+    fn test_file_args() {
+        let lang = Php::new();
+        let script = format!("/tmp/script{}", lang.extension());
+        let args = lang.args(Some(Path::new(&script)), vec!["--my-flag".into()]);
+        let expected_args = vec![script.as_str(), "--my-flag"];
+        assert_eq!(args, expected_args);
+    }
+
     #[test]
     fn test_php_binary() {
         let lang = Php::new();
         assert_eq!(lang.binary(), "php")
     }
+
+    #[test]
+    fn test_custom_binary() {
+        let mut code = CodeContainer::new();
+        code.open_new_group();
+        code.push("<?php echo \"check\" ?>".into());
+        code.close_group();
+
+        let lang = Php::with_binary("php8.2");
+        assert_eq!(lang.binary(), "php8.2");
+        let output = lang.export(code);
+        let expected_output = "#!/usr/bin/env php8.2\n\n<?php echo \"check\" ?>".to_string();
+        assert_eq!(output, expected_output);
+    }
 }
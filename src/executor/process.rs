@@ -0,0 +1,129 @@
+use std::io::{ErrorKind, Write};
+use std::process::{Child, Command, Stdio};
+
+use tempfile::TempPath;
+
+use crate::error::Error;
+
+use super::{alternatives, Context, Executor, Input, Job};
+
+/// A running interpreter. The temporary script file, if there is one, is
+/// removed when the process is dropped.
+pub struct Process {
+    pub child: Child,
+    _script: Option<TempPath>,
+    _job: Option<Job>,
+}
+
+impl Process {
+    pub fn new(child: Child, ctx: &Context) -> Process {
+        Process { _job: Job::new(&child, ctx), child, _script: None }
+    }
+}
+
+/// Starts the interpreter of the executor with the script. The script is
+/// passed as a temporary file or piped into stdin, depending on the context.
+pub fn spawn<E>(executor: &E, script: String, argv: Vec<String>, ctx: &Context) -> Result<Process, Error>
+where E: Executor + ?Sized {
+    match ctx.input {
+        Input::File => {
+            let path = write_script(&script, executor.extension())?;
+
+            let mut command = Command::new(executor.binary());
+            command.args(executor.args(Some(&path), argv));
+            let child = start(command, ctx)?;
+
+            Ok(Process { _job: Job::new(&child, ctx), child, _script: Some(path) })
+        },
+        Input::Stdin => {
+            let mut command = Command::new(executor.binary());
+            command.args(executor.args(None, argv));
+            command.stdin(Stdio::piped());
+            let mut child = start(command, ctx)?;
+
+            if let Some(mut stdin) = child.stdin.take() {
+                std::thread::spawn(move || {
+                    // The script can exit before it reads everything, that's
+                    // not an error on our side.
+                    if let Err(err) = stdin.write_all(script.as_bytes()) {
+                        if err.kind() != ErrorKind::BrokenPipe {
+                            eprintln!(" !! failed to write the script to stdin: {}", err);
+                        }
+                    }
+                });
+            }
+
+            Ok(Process::new(child, ctx))
+        },
+    }
+}
+
+/// Writes the script into a new temporary file, only readable by the current
+/// user.
+pub fn write_script(script: &str, extension: &str) -> Result<TempPath, Error> {
+    let mut file = tempfile::Builder::new()
+        .prefix("eval-md-")
+        .suffix(extension)
+        .tempfile()
+        .map_err(|err| Error::io("unable to create temporary script", err))?;
+
+    file.write_all(script.as_bytes())
+        .and_then(|_| file.flush())
+        .map_err(|err| Error::io(format!("unable to write {}", file.path().display()), err))?;
+
+    Ok(file.into_temp_path())
+}
+
+pub fn start(mut command: Command, ctx: &Context) -> Result<Child, Error> {
+    ctx.apply(&mut command);
+
+    // Both streams share the same file offset, so the output keeps its order
+    // like with `> file 2>&1`.
+    if let Some(file) = &ctx.capture {
+        let stdout = file.try_clone().map_err(|err| Error::io("unable to capture the output", err))?;
+        command.stdout(stdout);
+    }
+    if let Some(file) = ctx.capture_stderr.as_ref().or(ctx.capture.as_ref()) {
+        let stderr = file.try_clone().map_err(|err| Error::io("unable to capture the output", err))?;
+        command.stderr(stderr);
+    }
+
+    command.spawn().map_err(|err| spawn_error(&command, err))
+}
+
+pub fn spawn_error(command: &Command, err: std::io::Error) -> Error {
+    let binary = command.get_program().to_string_lossy().to_string();
+
+    match err.kind() {
+        ErrorKind::NotFound => Error::InterpreterNotFound {
+            alternatives: alternatives(&binary),
+            binary,
+        },
+        _ => Error::Spawn { binary, source: err },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_script() {
+        let path = write_script("echo 'check'", ".sh").unwrap();
+        let location = path.to_path_buf();
+
+        assert!(location.extension().is_some_and(|ext| ext == "sh"));
+        assert_eq!(std::fs::read_to_string(&location).unwrap(), "echo 'check'");
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = std::fs::metadata(&location).unwrap().permissions().mode();
+            assert_eq!(mode & 0o077, 0);
+        }
+
+        drop(path);
+        assert!(!location.exists());
+    }
+}
@@ -1,31 +1,50 @@
-use std::process::{Command, Stdio};
-use std::io::Write;
+use std::{env, ffi::OsString, io, path::{Path, PathBuf}};
 
 use crate::code_container::CodeContainer;
-
-use super::Executor;
-
-pub struct Python;
+use crate::error::Error;
+
+use super::{process, script_arg, session, shebang, Context, Driver, Executor, Process, Session};
+
+const PYTHON: &str = "python3";
+
+/// Evaluates the blocks in the same global namespace, like the interactive
+/// interpreter.
+const SESSION_DRIVER: &str = r#"
+import os, sys, traceback
+commands = os.fdopen({fd})
+scope = {"__name__": "__main__", "__builtins__": __builtins__}
+while True:
+    path = commands.readline().rstrip("\n")
+    if not path:
+        break
+    status = 0
+    try:
+        with open(path) as f:
+            exec(compile(f.read(), path, "exec"), scope)
+    except SystemExit as e:
+        status = e.code if isinstance(e.code, int) else int(e.code is not None)
+    except BaseException:
+        traceback.print_exc()
+        status = 1
+    sys.stderr.flush()
+    sys.stdout.write("{sentinel} %d\n" % status)
+    sys.stdout.flush()
+"#;
+
+pub struct Python {
+    binary: String,
+}
 
 impl Executor for Python {
-    fn exec(&self, script: CodeContainer, argv: Vec<String>) -> std::process::Child {
-        let mut prog = Command::new("python3")
-            .args(self.args(argv))
-            .stdin(Stdio::piped())
-            .spawn()
-            .unwrap();
+    fn exec(&self, script: CodeContainer, argv: Vec<String>, ctx: &Context) -> Result<Process, Error> {
+        let (python, ctx) = self.in_venv(ctx)?;
 
-        let mut stdin = prog.stdin.take().expect("Failed to open stdin");
-        std::thread::spawn(move || {
-            stdin.write_all(script.lines().as_bytes()).expect("Failed to write to stdin");
-        });
-
-        prog
+        process::spawn(&python, script.lines(), argv, &ctx)
     }
 
     fn export(&self, script: CodeContainer) -> String {
         let mut header: Vec<String> = vec![
-            "#!/usr/bin/env python3".into(),
+            shebang(&self.binary),
             "".into(),
         ];
 
@@ -34,23 +53,100 @@ impl Executor for Python {
         header.join("\n")
     }
 
-    fn binary(&self) -> &'static str {
-        "python3"
+    fn binary(&self) -> &str {
+        &self.binary
+    }
+
+    fn args(&self, script: Option<&Path>, args: Vec<String>) -> Vec<String> {
+        let mut argv = vec![script_arg(script, "-")];
+        argv.extend(args);
+        argv
+    }
+
+    fn extension(&self) -> &'static str {
+        ".py"
+    }
+
+    fn session(&self, argv: Vec<String>, ctx: &Context) -> Result<Session, Error> {
+        let (python, ctx) = self.in_venv(ctx)?;
+
+        session::start(&python, argv, &ctx)
+    }
+
+    fn session_args(&self, driver: &Driver, argv: Vec<String>) -> Option<Vec<String>> {
+        let mut args = vec!["-c".to_string(), driver.render(SESSION_DRIVER)];
+        args.extend(argv);
+        Some(args)
     }
 }
 
 impl Python {
     pub fn new() -> Python {
-        Python{}
+        Python::with_binary(PYTHON)
     }
 
-    fn args(&self, args: Vec<String>) -> Vec<String> {
-        let mut argv = vec!["-".to_string()];
-        argv.extend(args);
-        argv
+    /// Interpreter with a custom binary name or path, for example a specific
+    /// version.
+    pub fn with_binary(binary: &str) -> Python {
+        Python { binary: binary.to_string() }
+    }
+
+    /// Interpreter and context in the virtual environment. A custom
+    /// interpreter is always used as it is, the virtual environment only
+    /// replaces the default one.
+    fn in_venv(&self, ctx: &Context) -> Result<(Python, Context), Error> {
+        let venv = match &ctx.venv {
+            Some(venv) if self.binary == PYTHON => venv,
+            _ => return Ok((Python::with_binary(&self.binary), ctx.clone())),
+        };
+
+        let binary = venv_python(venv);
+        if !binary.is_file() {
+            return Err(Error::io(
+                format!("invalid virtual environment {}", venv.display()),
+                io::ErrorKind::NotFound.into(),
+            ))
+        }
+
+        let mut ctx = ctx.clone();
+        ctx.env.extend(venv_env(venv, &ctx));
+
+        Ok((Python::with_binary(&binary.to_string_lossy()), ctx))
+    }
+}
+
+fn venv_bin(venv: &Path) -> PathBuf {
+    if cfg!(windows) {
+        venv.join("Scripts")
+    } else {
+        venv.join("bin")
     }
 }
 
+fn venv_python(venv: &Path) -> PathBuf {
+    venv_bin(venv).join(format!("python{}", env::consts::EXE_SUFFIX))
+}
+
+/// Variables set by the activate script of the virtual environment: its bin
+/// directory goes first in PATH.
+fn venv_env(venv: &Path, ctx: &Context) -> Vec<(String, String)> {
+    let path = ctx.env
+        .iter()
+        .rev()
+        .find(|(key, _)| key == "PATH")
+        .map(|(_, value)| OsString::from(value))
+        .or_else(|| env::var_os("PATH"))
+        .unwrap_or_default();
+
+    let dirs = [venv_bin(venv)].into_iter().chain(env::split_paths(&path));
+    let path = env::join_paths(dirs).unwrap_or(path);
+
+    vec![
+        ("VIRTUAL_ENV".into(), venv.to_string_lossy().to_string()),
+        ("PATH".into(), path.to_string_lossy().to_string()),
+    ]
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -71,14 +167,62 @@ mod tests {
     #[test]
     fn test_args() {
         let lang = Python::new();
-        let args = lang.args(vec!["--my-flag".into(), "-o".into(), "file".into()]);
+        let args = lang.args(None, vec!["--my-flag".into(), "-o".into(), "file".into()]);
         let expected_args = vec!["-", "--my-flag", "-o", "file"];
         assert_eq!(args, expected_args);
     }
 
+    #[test]
+    fn test_file_args() {
+        let lang = Python::new();
+        let args = lang.args(Some(Path::new("/tmp/script.py")), vec!["--my-flag".into()]);
+        let expected_args = vec!["/tmp/script.py", "--my-flag"];
+        assert_eq!(args, expected_args);
+    }
+
     #[test]
     fn test_python_binary() {
         let lang = Python::new();
         assert_eq!(lang.binary(), "python3")
     }
+
+    #[test]
+    fn test_custom_binary() {
+        let mut code = CodeContainer::new();
+        code.open_new_group();
+        code.push("print(\"check\")".into());
+        code.close_group();
+
+        let lang = Python::with_binary("/opt/venv/bin/python");
+        assert_eq!(lang.binary(), "/opt/venv/bin/python");
+        let output = lang.export(code);
+        let expected_output = "#!/opt/venv/bin/python\n\nprint(\"check\")".to_string();
+        assert_eq!(output, expected_output);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_venv_env() {
+        let ctx = Context {
+            env: vec![("PATH".into(), "/usr/bin:/bin".into())],
+            ..Context::default()
+        };
+
+        let env = venv_env(Path::new("/work/.venv"), &ctx);
+        assert_eq!(env, vec![
+            ("VIRTUAL_ENV".into(), "/work/.venv".into()),
+            ("PATH".into(), "/work/.venv/bin:/usr/bin:/bin".into()),
+        ]);
+        assert_eq!(venv_python(Path::new("/work/.venv")), PathBuf::from("/work/.venv/bin/python"));
+    }
+
+    #[test]
+    fn test_session_args() {
+        let driver = Driver { sentinel: "SENTINEL".into(), fd: 3 };
+        let args = Python::new().session_args(&driver, vec!["a".into()]).unwrap();
+        assert_eq!(args[0], "-c");
+        assert!(args[1].contains("SENTINEL"));
+        assert!(args[1].contains("fdopen(3)"));
+        assert_eq!(args[2], "a");
+    }
 }
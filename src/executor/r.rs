@@ -0,0 +1,80 @@
+use std::path::Path;
+
+use crate::code_container::CodeContainer;
+
+use super::{script_arg, Executor};
+
+pub struct R;
+
+impl Executor for R {
+    fn export(&self, script: CodeContainer) -> String {
+        let mut header: Vec<String> = vec![
+            "#!/usr/bin/env Rscript".into(),
+            "".into(),
+        ];
+
+        header.push(script.lines());
+
+        header.join("\n")
+    }
+
+    fn binary(&self) -> &'static str {
+        "Rscript"
+    }
+
+    fn args(&self, script: Option<&Path>, args: Vec<String>) -> Vec<String> {
+        let mut argv = vec![script_arg(script, "-")];
+        argv.extend(args);
+        argv
+    }
+
+    fn extension(&self) -> &'static str {
+        ".R"
+    }
+}
+
+impl R {
+    pub fn new() -> R {
+        R{}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export() {
+        let mut code = CodeContainer::new();
+        code.open_new_group();
+        code.push("cat(\"check\\n\")".into());
+        code.close_group();
+
+        let lang = R::new();
+        let output = lang.export(code);
+        let expected_output = "#!/usr/bin/env Rscript\n\ncat(\"check\\n\")".to_string();
+        assert_eq!(output, expected_output);
+    }
+
+    #[test]
+    fn test_args() {
+        let lang = R::new();
+        let args = lang.args(None, vec!["--my-flag".into(), "-o".into(), "file".into()]);
+        let expected_args = vec!["-", "--my-flag", "-o", "file"];
+        assert_eq!(args, expected_args);
+    }
+
+    #[test]
+    fn test_file_args() {
+        let lang = R::new();
+        let args = lang.args(Some(Path::new("/tmp/script.R")), vec!["--my-flag".into()]);
+        let expected_args = vec!["/tmp/script.R", "--my-flag"];
+        assert_eq!(args, expected_args);
+    }
+
+    #[test]
+    fn test_r_binary() {
+        let lang = R::new();
+        assert_eq!(lang.binary(), "Rscript")
+    }
+}
@@ -0,0 +1,139 @@
+use crate::error::Error;
+
+use super::{aliases, custom_binary, language_picker, resolve_alias, supported_languages, Custom, Executor};
+
+/// Built-in languages together with the ones from the configuration files.
+/// User defined languages take precedence over built-in ones with the same
+/// name.
+#[derive(Debug, Default)]
+pub struct Registry {
+    custom: Vec<Custom>,
+}
+
+impl Registry {
+    pub fn new(custom: Vec<Custom>) -> Registry {
+        Registry { custom }
+    }
+
+    /// Executor for code blocks with the given tag. If the executor is not
+    /// a known language, it's used as the interpreter binary of the tag's
+    /// language, for example `py:python3.12`.
+    pub fn pick(&self, tag: &str, executor: &str) -> Result<Box<dyn Executor>, Error> {
+        let custom = self.custom
+            .iter()
+            .find(|c| c.name == executor || c.aliases.iter().any(|a| a == executor));
+
+        match custom {
+            Some(custom) => Ok(Box::new(custom.clone())),
+            None => language_picker(executor).or_else(|err| match err {
+                Error::UnknownLanguage(_) if tag != executor => custom_binary(tag, executor).map_err(|_| err),
+                err => Err(err),
+            }),
+        }
+    }
+
+    pub fn supported_languages(&self) -> Vec<String> {
+        let mut list = supported_languages()
+            .into_iter()
+            .map(String::from)
+            .chain(self.custom.iter().map(|c| c.name.clone()))
+            .collect::<Vec<String>>();
+        list.sort();
+        list.dedup();
+        list
+    }
+
+    /// Name of the language of a tag or an executor, for example "python"
+    /// for `py`, or "fennel" for the `fnl` alias of a configured language.
+    pub fn language(&self, name: &str) -> Option<String> {
+        let custom = self.custom
+            .iter()
+            .find(|c| c.name == name || c.aliases.iter().any(|a| a == name));
+        if let Some(custom) = custom {
+            return Some(custom.name.clone())
+        }
+
+        match supported_languages().contains(&name) {
+            true => Some(name.to_string()),
+            false => resolve_alias(name).map(|(language, _)| language.to_string()),
+        }
+    }
+
+    pub fn aliases(&self) -> Vec<(String, String, String)> {
+        let custom = self.custom.iter().flat_map(|c| {
+            c.aliases.iter().map(|a| (a.clone(), c.name.clone(), c.binary.clone()))
+        });
+
+        aliases()
+            .into_iter()
+            .map(|(a, n, e)| (a.to_string(), n.to_string(), e.to_string()))
+            .chain(custom)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> Registry {
+        let mut fennel = Custom::new("fennel", "fennel");
+        fennel.aliases = vec!["fnl".into()];
+
+        Registry::new(vec![fennel, Custom::new("python", "python3.12")])
+    }
+
+    #[test]
+    fn test_pick() {
+        let test_cases: Vec<(&str, &str, Option<&str>)> = vec![
+            ("fennel", "fennel", Some("fennel")),
+            ("fennel", "fnl", Some("fennel")),
+            ("python", "python", Some("python3.12")),
+            ("sh", "bash", Some("bash")),
+            ("something", "something", None),
+            ("lua", "luajit", Some("luajit")),
+            ("py", "/opt/venv/bin/python", Some("/opt/venv/bin/python")),
+            ("lua", "python", Some("python3.12")),
+            ("python3.12", "python3.12", None),
+            ("bash", "mksh", None),
+        ];
+
+        let registry = registry();
+        for case in test_cases {
+            let result = registry.pick(case.0, case.1).ok();
+            assert_eq!(result.as_ref().map(|e| e.binary()), case.2);
+        }
+    }
+
+    #[test]
+    fn test_supported_languages() {
+        let list = registry().supported_languages();
+
+        assert!(list.contains(&"fennel".to_string()));
+        assert_eq!(list.iter().filter(|l| *l == "python").count(), 1);
+    }
+
+    #[test]
+    fn test_language() {
+        let test_cases: Vec<(&str, Option<&str>)> = vec![
+            ("fnl", Some("fennel")),
+            ("fennel", Some("fennel")),
+            ("py", Some("python")),
+            ("bash", Some("shell")),
+            ("python3.12", None),
+        ];
+
+        let registry = registry();
+        for case in test_cases {
+            assert_eq!(registry.language(case.0).as_deref(), case.1);
+        }
+    }
+
+    #[test]
+    fn test_aliases() {
+        let list = registry().aliases();
+
+        assert!(list.contains(&("fnl".into(), "fennel".into(), "fennel".into())));
+        assert!(list.contains(&("bash".into(), "shell".into(), "bash".into())));
+    }
+}
@@ -1,31 +1,40 @@
-use std::process::{Command, Stdio};
-use std::io::Write;
+use std::path::Path;
 
 use crate::code_container::CodeContainer;
 
-use super::Executor;
-
-pub struct Ruby;
+use super::{script_arg, shebang, Driver, Executor};
+
+/// Evaluates the blocks with the same binding, so local variables are kept
+/// between them.
+const SESSION_DRIVER: &str = r#"
+$stdout.sync = true
+commands = IO.new({fd})
+scope = TOPLEVEL_BINDING
+while (path = commands.gets)
+  path = path.chomp
+  break if path.empty?
+  status = 0
+  begin
+    scope.eval(File.read(path), path, 1)
+  rescue SystemExit => e
+    status = e.status
+  rescue Exception => e
+    $stderr.puts e.full_message
+    status = 1
+  end
+  $stderr.flush
+  puts "{sentinel} #{status}"
+end
+"#;
+
+pub struct Ruby {
+    binary: String,
+}
 
 impl Executor for Ruby {
-    fn exec(&self, script: CodeContainer, argv: Vec<String>) -> std::process::Child {
-        let mut prog = Command::new("ruby")
-            .args(self.args(argv))
-            .stdin(Stdio::piped())
-            .spawn()
-            .unwrap();
-
-        let mut stdin = prog.stdin.take().expect("Failed to open stdin");
-        std::thread::spawn(move || {
-            stdin.write_all(script.lines().as_bytes()).expect("Failed to write to stdin");
-        });
-
-        prog
-    }
-
     fn export(&self, script: CodeContainer) -> String {
         let mut header: Vec<String> = vec![
-            "#!/usr/bin/env ruby".into(),
+            shebang(&self.binary),
             "".into(),
         ];
 
@@ -34,20 +43,36 @@ impl Executor for Ruby {
         header.join("\n")
     }
 
-    fn binary(&self) -> &'static str {
-        "ruby"
+    fn binary(&self) -> &str {
+        &self.binary
+    }
+
+    fn args(&self, script: Option<&Path>, args: Vec<String>) -> Vec<String> {
+        let mut argv = vec![script_arg(script, "-")];
+        argv.extend(args);
+        argv
+    }
+
+    fn extension(&self) -> &'static str {
+        ".rb"
+    }
+
+    fn session_args(&self, driver: &Driver, argv: Vec<String>) -> Option<Vec<String>> {
+        let mut args = vec!["-e".to_string(), driver.render(SESSION_DRIVER)];
+        args.extend(argv);
+        Some(args)
     }
 }
 
 impl Ruby {
     pub fn new() -> Ruby {
-        Ruby{}
+        Ruby::with_binary("ruby")
     }
 
-    fn args(&self, args: Vec<String>) -> Vec<String> {
-        let mut argv = vec!["-".to_string()];
-        argv.extend(args);
-        argv
+    /// Interpreter with a custom binary name or path, for example a specific
+    /// version.
+    pub fn with_binary(binary: &str) -> Ruby {
+        Ruby { binary: binary.to_string() }
     }
 }
 
@@ -71,14 +96,37 @@ mod tests {
     #[test]
     fn test_args() {
         let lang = Ruby::new();
-        let args = lang.args(vec!["--my-flag".into(), "-o".into(), "file".into()]);
+        let args = lang.args(None, vec!["--my-flag".into(), "-o".into(), "file".into()]);
         let expected_args = vec!["-", "--my-flag", "-o", "file"];
         assert_eq!(args, expected_args);
     }
 
+    #[test]
+    fn test_file_args() {
+        let lang = Ruby::new();
+        let script = format!("/tmp/script{}", lang.extension());
+        let args = lang.args(Some(Path::new(&script)), vec!["--my-flag".into()]);
+        let expected_args = vec![script.as_str(), "--my-flag"];
+        assert_eq!(args, expected_args);
+    }
+
     #[test]
     fn test_ruby_binary() {
         let lang = Ruby::new();
         assert_eq!(lang.binary(), "ruby")
     }
+
+    #[test]
+    fn test_custom_binary() {
+        let mut code = CodeContainer::new();
+        code.open_new_group();
+        code.push("puts \"check\"".into());
+        code.close_group();
+
+        let lang = Ruby::with_binary("jruby");
+        assert_eq!(lang.binary(), "jruby");
+        let output = lang.export(code);
+        let expected_output = "#!/usr/bin/env jruby\n\nputs \"check\"".to_string();
+        assert_eq!(output, expected_output);
+    }
 }
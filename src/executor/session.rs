@@ -0,0 +1,272 @@
+use std::io::{self, BufRead, BufReader, PipeReader, PipeWriter, Write};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant, SystemTime};
+use std::thread;
+
+use crate::error::Error;
+use crate::timeout;
+
+use super::{compiled::hash, process, Context, Executor, Job};
+
+/// A persistent interpreter that evaluates code blocks one by one, so the
+/// state is kept between them.
+///
+/// The interpreter runs a small driver program. The driver reads the path of
+/// a code block from its own pipe, so stdin is left for the blocks. It
+/// evaluates the block in the same context as the previous ones, then prints
+/// the sentinel and the status of the block on stdout:
+///
+/// ```text
+/// __eval_md_0123456789abcdef__ 0
+/// ```
+pub struct Session {
+    child: Child,
+    _job: Option<Job>,
+    commands: Option<PipeWriter>,
+    lines: Receiver<io::Result<Vec<u8>>>,
+    sentinel: String,
+    extension: String,
+}
+
+/// Result of a code block evaluated in a session.
+#[derive(Debug, Eq, PartialEq)]
+pub enum Outcome {
+    /// The block finished with the given status.
+    Done(i32),
+    /// The interpreter exited during the block.
+    Exited(ExitStatus),
+    /// The block did not finish in time, the interpreter was terminated.
+    TimedOut,
+}
+
+/// Placeholders of a driver program: the sentinel, and the file descriptor
+/// the paths of the blocks can be read from.
+pub struct Driver {
+    pub sentinel: String,
+    pub fd: i32,
+}
+
+impl Driver {
+    pub fn render(&self, program: &str) -> String {
+        program
+            .replace("{sentinel}", &self.sentinel)
+            .replace("{fd}", &self.fd.to_string())
+    }
+}
+
+/// Starts the driver of the executor.
+pub fn start<E>(executor: &E, argv: Vec<String>, ctx: &Context) -> Result<Session, Error>
+where E: Executor + ?Sized {
+    let unsupported = || Error::Unsupported(format!("sessions are not supported with {}", executor.binary()));
+    let (reader, writer) = io::pipe().map_err(|err| Error::io("unable to create the session pipe", err))?;
+    let driver = Driver { sentinel: new_sentinel(), fd: raw_fd(&reader).ok_or_else(unsupported)? };
+    let args = executor.session_args(&driver, argv).ok_or_else(unsupported)?;
+
+    let mut command = Command::new(executor.binary());
+    command.args(args);
+
+    spawn(command, reader, writer, driver.sentinel, executor.extension(), ctx)
+}
+
+#[cfg(unix)]
+fn raw_fd(reader: &PipeReader) -> Option<i32> {
+    use std::os::fd::AsRawFd;
+
+    Some(reader.as_raw_fd())
+}
+
+#[cfg(not(unix))]
+fn raw_fd(_reader: &PipeReader) -> Option<i32> {
+    None
+}
+
+/// The read end of the pipe is inherited by the driver, stdin is not touched.
+fn spawn(mut command: Command, reader: PipeReader, writer: PipeWriter, sentinel: String, extension: &str, ctx: &Context) -> Result<Session, Error> {
+    #[cfg(unix)]
+    {
+        use std::os::{fd::AsRawFd, unix::process::CommandExt};
+
+        // The pipe is closed on exec, the driver's copy has to stay open.
+        let fd = reader.as_raw_fd();
+        unsafe {
+            command.pre_exec(move || match libc::fcntl(fd, libc::F_SETFD, 0) {
+                -1 => Err(io::Error::last_os_error()),
+                _ => Ok(()),
+            });
+        }
+    }
+    command.stdout(Stdio::piped());
+    let mut child = process::start(command, ctx)?;
+    drop(reader);
+
+    let stdout = child.stdout.take();
+    let (sender, lines) = mpsc::channel();
+    if let Some(stdout) = stdout {
+        thread::spawn(move || {
+            let mut reader = BufReader::new(stdout);
+            loop {
+                let mut line = vec![];
+                match reader.read_until(b'\n', &mut line) {
+                    Ok(0) => break,
+                    Ok(_) => if sender.send(Ok(line)).is_err() { break },
+                    Err(err) => {
+                        let _ = sender.send(Err(err));
+                        break
+                    },
+                }
+            }
+        });
+    }
+
+    Ok(Session { _job: Job::new(&child, ctx), child, commands: Some(writer), lines, sentinel, extension: extension.to_string() })
+}
+
+impl Session {
+    /// Evaluates a code block, its output is forwarded to `out` until the
+    /// sentinel arrives.
+    pub fn eval(&mut self, code: &str, limit: Option<Duration>, out: &mut dyn Write) -> Result<Outcome, Error> {
+        let path = process::write_script(code, &self.extension)?;
+        let deadline = limit.map(|limit| Instant::now() + limit);
+
+        let request = format!("{}\n", path.to_string_lossy());
+        let sent = self.commands
+            .as_mut()
+            .map(|commands| commands.write_all(request.as_bytes()).and_then(|_| commands.flush()));
+        if let Some(Err(err)) = sent {
+            if err.kind() != io::ErrorKind::BrokenPipe {
+                return Err(Error::io("failed to send the block to the session", err))
+            }
+        }
+
+        loop {
+            let received = match deadline {
+                Some(deadline) => self.lines.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+                None => self.lines.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+
+            let line = match received {
+                Ok(line) => line.map_err(|err| Error::io("failed to read the session output", err))?,
+                Err(RecvTimeoutError::Timeout) => {
+                    timeout::terminate(&mut self.child)
+                        .map_err(|err| Error::io("failed to terminate the session", err))?;
+                    return Ok(Outcome::TimedOut)
+                },
+                Err(RecvTimeoutError::Disconnected) => {
+                    let status = self.child.wait()
+                        .map_err(|err| Error::io("failed to wait for the session", err))?;
+                    return Ok(Outcome::Exited(status))
+                },
+            };
+
+            let (output, status) = split_sentinel(&line, &self.sentinel);
+            let written = match status {
+                Some(_) if output.is_empty() => Ok(()),
+                Some(_) => out.write_all(output).and_then(|_| out.write_all(b"\n")),
+                None => out.write_all(output),
+            };
+            written
+                .and_then(|_| out.flush())
+                .map_err(|err| Error::io("failed to write the output", err))?;
+
+            if let Some(status) = status {
+                return Ok(Outcome::Done(status))
+            }
+        }
+    }
+
+    /// Closes the pipe of the driver, so it exits, and waits for it.
+    pub fn finish(mut self) -> Result<ExitStatus, Error> {
+        drop(self.commands.take());
+
+        self.child.wait().map_err(|err| Error::io("failed to wait for the session", err))
+    }
+}
+
+/// Unique marker of the end of the blocks, it should never appear in the
+/// output of the code.
+fn new_sentinel() -> String {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string();
+
+    format!("__eval_md_{:016x}__", hash(&[&std::process::id().to_string(), &now]))
+}
+
+/// Splits a line of output into the output of the code and the status after
+/// the sentinel, if there is one. Output without a trailing newline ends up
+/// on the same line as the sentinel.
+fn split_sentinel<'a>(line: &'a [u8], sentinel: &str) -> (&'a [u8], Option<i32>) {
+    let marker = sentinel.as_bytes();
+    let position = line.windows(marker.len()).rposition(|window| window == marker);
+
+    let Some(position) = position else {
+        return (line, None)
+    };
+
+    let status = String::from_utf8_lossy(&line[position + marker.len()..])
+        .trim()
+        .parse::<i32>()
+        .ok();
+
+    match status {
+        Some(status) => (&line[..position], Some(status)),
+        None => (line, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Seek;
+
+    use super::*;
+
+    #[test]
+    fn test_split_sentinel() {
+        let sentinel = "__eval_md_test__";
+        let test_cases: Vec<(&str, &str, Option<i32>)> = vec![
+            ("hello\n", "hello\n", None),
+            ("__eval_md_test__ 0\n", "", Some(0)),
+            ("no newline__eval_md_test__ 1\n", "no newline", Some(1)),
+            ("__eval_md_test__ x\n", "__eval_md_test__ x\n", None),
+        ];
+
+        for case in test_cases {
+            let (output, status) = split_sentinel(case.0.as_bytes(), sentinel);
+            assert_eq!((output, status), (case.1.as_bytes(), case.2));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_block_reads_stdin() {
+        use crate::executor::Shell;
+
+        let (reader, writer) = io::pipe().unwrap();
+        let driver = Driver { sentinel: new_sentinel(), fd: raw_fd(&reader).unwrap() };
+        let mut input = tempfile::tempfile().unwrap();
+        input.write_all(b"typed\n").unwrap();
+        input.rewind().unwrap();
+
+        let mut command = Command::new("sh");
+        command.args(Shell::Sh.session_args(&driver, vec![]).unwrap());
+        command.stdin(input);
+        let mut session = spawn(command, reader, writer, driver.sentinel, ".sh", &Context::default()).unwrap();
+
+        let mut output = vec![];
+        assert_eq!(session.eval("read -r line", None, &mut output).unwrap(), Outcome::Done(0));
+        assert_eq!(session.eval("echo \"$line\"", None, &mut output).unwrap(), Outcome::Done(0));
+        assert_eq!(output, b"typed\n");
+        assert!(session.finish().unwrap().success());
+    }
+
+    #[test]
+    fn test_new_sentinel() {
+        let sentinel = new_sentinel();
+
+        assert!(sentinel.starts_with("__eval_md_"));
+        assert!(sentinel.ends_with("__"));
+    }
+}
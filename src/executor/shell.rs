@@ -1,31 +1,37 @@
-use std::process::{Command, Stdio};
-use std::io::Write;
+use std::path::Path;
 
 use crate::code_container::CodeContainer;
+use crate::error::Error;
 
-use super::Executor;
+use super::{process, script_arg, Context, Driver, Executor, Input, Process};
 
-#[derive(Default)]
+/// Sources the blocks one by one into the same shell.
+const SESSION_DRIVER: &str = r#"
+while IFS= read -r __eval_md_path <&{fd} && [ -n "$__eval_md_path" ]; do
+  . "$__eval_md_path"
+  printf '%s %d\n' '{sentinel}' "$?"
+done
+"#;
+
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
 pub enum Shell {
     Bash,
+    Dash,
+    Fish,
+    Ksh,
+    Pwsh,
+    Sh,
     #[default]
     Zsh,
 }
 
 impl Executor for Shell {
-    fn exec(&self, script: CodeContainer, argv: Vec<String>) -> std::process::Child {
-        let mut prog = Command::new(self.target_str())
-            .args(self.args(argv))
-            .stdin(Stdio::piped())
-            .spawn()
-            .unwrap();
-
-        let mut stdin = prog.stdin.take().expect("Failed to open stdin");
-        std::thread::spawn(move || {
-            stdin.write_all(script.lines().as_bytes()).expect("Failed to write to stdin");
-        });
+    fn exec(&self, script: CodeContainer, argv: Vec<String>, ctx: &Context) -> Result<Process, Error> {
+        if *self == Shell::Pwsh && ctx.input == Input::Stdin && !argv.is_empty() {
+            return Err(Error::Unsupported("pwsh does not accept script arguments with --input stdin".into()))
+        }
 
-        prog
+        process::spawn(self, script.lines(), argv, ctx)
     }
 
     fn export(&self, script: CodeContainer) -> String {
@@ -42,29 +48,66 @@ impl Executor for Shell {
     fn binary(&self) -> &'static str {
         self.target_str()
     }
+
+    fn args(&self, script: Option<&Path>, args: Vec<String>) -> Vec<String> {
+        let mut argv: Vec<String> = match (self, script) {
+            // Pwsh runs only .ps1 files with -File, and it can't pass
+            // arguments to a script from stdin.
+            (Shell::Pwsh, Some(path)) => vec!["-NoProfile".into(), "-File".into(), script_arg(Some(path), "")],
+            (Shell::Pwsh, None) => return vec!["-NoProfile".into(), "-Command".into(), "-".into()],
+            // POSIX way to read the script from stdin with arguments.
+            (Shell::Sh | Shell::Dash | Shell::Ksh, None) => vec!["-s".into(), "--".into()],
+            _ => vec![script_arg(script, "/dev/stdin")],
+        };
+        argv.extend(args);
+        argv
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            Shell::Fish => ".fish",
+            Shell::Pwsh => ".ps1",
+            _ => ".sh",
+        }
+    }
+
+    fn session_args(&self, driver: &Driver, argv: Vec<String>) -> Option<Vec<String>> {
+        if let Shell::Fish | Shell::Pwsh = self {
+            return None
+        }
+
+        // The first argument after the command is $0.
+        let mut args = vec!["-c".to_string(), driver.render(SESSION_DRIVER), "eval-md".into()];
+        args.extend(argv);
+        Some(args)
+    }
 }
 
 impl Shell {
-    pub fn new(shell: &str) -> Shell {
+    pub fn new(shell: &str) -> Result<Shell, Error> {
         match shell {
-            "bash" => Shell::Bash,
-            "zsh" => Shell::Zsh,
-            _ => Shell::default(),
+            "bash" => Ok(Shell::Bash),
+            "dash" => Ok(Shell::Dash),
+            "fish" => Ok(Shell::Fish),
+            "ksh" => Ok(Shell::Ksh),
+            "pwsh" => Ok(Shell::Pwsh),
+            "sh" => Ok(Shell::Sh),
+            "zsh" => Ok(Shell::Zsh),
+            _ => Err(Error::UnknownLanguage(format!("shell:{}", shell))),
         }
     }
 
     fn target_str(&self) -> &'static str {
         match self {
             Shell::Bash => "bash",
+            Shell::Dash => "dash",
+            Shell::Fish => "fish",
+            Shell::Ksh => "ksh",
+            Shell::Pwsh => "pwsh",
+            Shell::Sh => "sh",
             Shell::Zsh => "zsh",
         }
     }
-
-    fn args(&self, args: Vec<String>) -> Vec<String> {
-        let mut argv = vec!["/dev/stdin".to_string()];
-        argv.extend(args);
-        argv
-    }
 }
 
 #[cfg(test)]
@@ -87,7 +130,7 @@ mod tests {
     #[test]
     fn test_default_args() {
         let lang = Shell::default();
-        let args = lang.args(vec!["--my-flag".into(), "-o".into(), "file".into()]);
+        let args = lang.args(None, vec!["--my-flag".into(), "-o".into(), "file".into()]);
         let expected_args = vec!["/dev/stdin", "--my-flag", "-o", "file"];
         assert_eq!(args, expected_args);
     }
@@ -99,7 +142,7 @@ mod tests {
         code.push("echo \"check\"".into());
         code.close_group();
 
-        let lang = Shell::new("zsh");
+        let lang = Shell::new("zsh").unwrap();
         let output = lang.export(code);
         let expected_output = "#!/usr/bin/env zsh\n\necho \"check\"".to_string();
         assert_eq!(output, expected_output);
@@ -107,8 +150,8 @@ mod tests {
 
     #[test]
     fn test_zsh_args() {
-        let lang = Shell::new("zsh");
-        let args = lang.args(vec!["--my-flag".into(), "-o".into(), "file".into()]);
+        let lang = Shell::new("zsh").unwrap();
+        let args = lang.args(None, vec!["--my-flag".into(), "-o".into(), "file".into()]);
         let expected_args = vec!["/dev/stdin", "--my-flag", "-o", "file"];
         assert_eq!(args, expected_args);
     }
@@ -120,7 +163,7 @@ mod tests {
         code.push("echo \"check\"".into());
         code.close_group();
 
-        let lang = Shell::new("bash");
+        let lang = Shell::new("bash").unwrap();
         let output = lang.export(code);
         let expected_output = "#!/usr/bin/env bash\n\necho \"check\"".to_string();
         assert_eq!(output, expected_output);
@@ -128,21 +171,113 @@ mod tests {
 
     #[test]
     fn test_bash_args() {
-        let lang = Shell::new("bash");
-        let args = lang.args(vec!["--my-flag".into(), "-o".into(), "file".into()]);
+        let lang = Shell::new("bash").unwrap();
+        let args = lang.args(None, vec!["--my-flag".into(), "-o".into(), "file".into()]);
         let expected_args = vec!["/dev/stdin", "--my-flag", "-o", "file"];
         assert_eq!(args, expected_args);
     }
 
+    #[test]
+    fn test_bash_file_args() {
+        let lang = Shell::new("bash").unwrap();
+        let args = lang.args(Some(Path::new("/tmp/script.sh")), vec!["--my-flag".into()]);
+        let expected_args = vec!["/tmp/script.sh", "--my-flag"];
+        assert_eq!(args, expected_args);
+    }
+
     #[test]
     fn test_bash_binary() {
-        let lang = Shell::new("bash");
+        let lang = Shell::new("bash").unwrap();
         assert_eq!(lang.binary(), "bash")
     }
 
     #[test]
     fn test_zsh_binary() {
-        let lang = Shell::new("zsh");
+        let lang = Shell::new("zsh").unwrap();
         assert_eq!(lang.binary(), "zsh")
     }
+
+    #[test]
+    fn test_new_unknown() {
+        assert!(Shell::new("something").is_err());
+        assert!(Shell::new("").is_err());
+    }
+
+    #[test]
+    fn test_export_shebang() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            ("sh", "#!/usr/bin/env sh"),
+            ("dash", "#!/usr/bin/env dash"),
+            ("ksh", "#!/usr/bin/env ksh"),
+            ("fish", "#!/usr/bin/env fish"),
+            ("pwsh", "#!/usr/bin/env pwsh"),
+        ];
+
+        for case in test_cases {
+            let mut code = CodeContainer::new();
+            code.open_new_group();
+            code.push("echo \"check\"".into());
+            code.close_group();
+
+            let output = Shell::new(case.0).unwrap().export(code);
+            assert_eq!(output, format!("{}\n\necho \"check\"", case.1));
+        }
+    }
+
+    #[test]
+    fn test_stdin_args() {
+        let test_cases: Vec<(&str, Vec<&str>)> = vec![
+            ("sh", vec!["-s", "--", "--my-flag"]),
+            ("dash", vec!["-s", "--", "--my-flag"]),
+            ("ksh", vec!["-s", "--", "--my-flag"]),
+            ("fish", vec!["/dev/stdin", "--my-flag"]),
+            ("pwsh", vec!["-NoProfile", "-Command", "-"]),
+        ];
+
+        for case in test_cases {
+            let args = Shell::new(case.0).unwrap().args(None, vec!["--my-flag".into()]);
+            assert_eq!(args, case.1);
+        }
+    }
+
+    #[test]
+    fn test_pwsh_stdin_argv() {
+        let ctx = Context { input: Input::Stdin, ..Context::default() };
+        let result = Shell::Pwsh.exec(CodeContainer::new(), vec!["--my-flag".into()], &ctx);
+        assert!(matches!(result, Err(Error::Unsupported(_))));
+    }
+
+    #[test]
+    fn test_file_args() {
+        let test_cases: Vec<(&str, Vec<&str>)> = vec![
+            ("sh", vec!["/tmp/script", "--my-flag"]),
+            ("fish", vec!["/tmp/script", "--my-flag"]),
+            ("pwsh", vec!["-NoProfile", "-File", "/tmp/script", "--my-flag"]),
+        ];
+
+        for case in test_cases {
+            let args = Shell::new(case.0).unwrap().args(Some(Path::new("/tmp/script")), vec!["--my-flag".into()]);
+            assert_eq!(args, case.1);
+        }
+    }
+
+    #[test]
+    fn test_extension() {
+        assert_eq!(Shell::Sh.extension(), ".sh");
+        assert_eq!(Shell::Fish.extension(), ".fish");
+        assert_eq!(Shell::Pwsh.extension(), ".ps1");
+    }
+
+    #[test]
+    fn test_session_args() {
+        let driver = Driver { sentinel: "SENTINEL".into(), fd: 3 };
+        let args = Shell::Bash.session_args(&driver, vec!["a".into()]).unwrap();
+        assert_eq!(args[0], "-c");
+        assert!(args[1].contains("'SENTINEL'"));
+        assert!(args[1].contains("<&3"));
+        assert_eq!(&args[2..], ["eval-md", "a"]);
+
+        assert_eq!(Shell::Fish.session_args(&driver, vec![]), None);
+        assert_eq!(Shell::Pwsh.session_args(&driver, vec![]), None);
+    }
 }
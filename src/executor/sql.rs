@@ -0,0 +1,140 @@
+use std::path::Path;
+
+use crate::code_block_options::CB_OPTION_DB;
+use crate::code_container::CodeContainer;
+use crate::error::Error;
+
+use super::{process, Context, Executor, Process};
+
+/// Database of the sqlite3 shell when no database is selected.
+const IN_MEMORY: &str = ":memory:";
+
+/// SQL code blocks evaluated with the sqlite3 shell. The database can be
+/// selected with `--db` or with the "db" option on the code blocks, an
+/// in-memory database is used by default.
+#[derive(Debug, Default, Clone)]
+pub struct Sql {
+    database: Option<String>,
+}
+
+impl Executor for Sql {
+    fn exec(&self, script: CodeContainer, argv: Vec<String>, ctx: &Context) -> Result<Process, Error> {
+        // Extra arguments would be evaluated as SQL by sqlite3.
+        if !argv.is_empty() {
+            return Err(Error::Unsupported("sqlite3 does not accept script arguments".into()))
+        }
+        let sql = Sql::for_script(&script, ctx);
+
+        process::spawn(&sql, script.lines(), argv, ctx)
+    }
+
+    fn export(&self, script: CodeContainer) -> String {
+        script.lines()
+    }
+
+    fn binary(&self) -> &'static str {
+        "sqlite3"
+    }
+
+    fn args(&self, script: Option<&Path>, _args: Vec<String>) -> Vec<String> {
+        let mut argv = vec![
+            "-bail".to_string(),
+            "-table".to_string(),
+            self.database.clone().unwrap_or_else(|| IN_MEMORY.into()),
+        ];
+        if let Some(path) = script {
+            // Arguments of dot-commands are quoted like C strings.
+            let path = path.to_string_lossy().replace('\\', "\\\\").replace('"', "\\\"");
+            argv.push(format!(".read \"{}\"", path));
+        }
+        argv
+    }
+
+    fn extension(&self) -> &'static str {
+        ".sql"
+    }
+}
+
+impl Sql {
+    pub fn new() -> Sql {
+        Sql::default()
+    }
+
+    /// Database of the script, the "db" option on the code blocks overrides
+    /// the one from the command line. Relative paths are resolved from the
+    /// working directory of the script.
+    fn for_script(script: &CodeContainer, ctx: &Context) -> Sql {
+        let database = script.blocks()
+            .iter()
+            .find_map(|b| b.option(CB_OPTION_DB))
+            .or_else(|| ctx.database.as_ref().map(|db| db.to_string_lossy().to_string()));
+
+        Sql { database }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::code_block_options::CodeBlockOption;
+
+    #[test]
+    fn test_export() {
+        let mut code = CodeContainer::new();
+        code.open_new_group();
+        code.push("select 1;".into());
+        code.close_group();
+
+        let lang = Sql::new();
+        let output = lang.export(code);
+        assert_eq!(output, "select 1;");
+    }
+
+    #[test]
+    fn test_args() {
+        let lang = Sql::new();
+        let args = lang.args(None, vec!["--my-flag".into()]);
+        let expected_args = vec!["-bail", "-table", ":memory:"];
+        assert_eq!(args, expected_args);
+    }
+
+    #[test]
+    fn test_file_args() {
+        let lang = Sql { database: Some("db.sqlite".into()) };
+        let args = lang.args(Some(Path::new("/tmp/it's \"a\".sql")), vec![]);
+        let expected_args = vec!["-bail", "-table", "db.sqlite", ".read \"/tmp/it's \\\"a\\\".sql\""];
+        assert_eq!(args, expected_args);
+    }
+
+    #[test]
+    fn test_exec_argv() {
+        let result = Sql::new().exec(CodeContainer::new(), vec!["select 1;".into()], &Context::default());
+        assert!(matches!(result, Err(Error::Unsupported(_))));
+    }
+
+    #[test]
+    fn test_for_script() {
+        let mut code = CodeContainer::new();
+        code.open_block(1, "sql", CodeBlockOption::parse_options("```sql"));
+        code.push("select 1;".into());
+        code.close_group();
+
+        let ctx = Context { database: Some(PathBuf::from("/data/cli.sqlite")), ..Context::default() };
+        assert_eq!(Sql::for_script(&code, &Context::default()).database, None);
+        assert_eq!(Sql::for_script(&code, &ctx).database.as_deref(), Some("/data/cli.sqlite"));
+
+        code.open_block(5, "sql", CodeBlockOption::parse_options("```sql #db=docs.sqlite"));
+        code.push("select 2;".into());
+        code.close_group();
+
+        assert_eq!(Sql::for_script(&code, &ctx).database.as_deref(), Some("docs.sqlite"));
+    }
+
+    #[test]
+    fn test_sql_binary() {
+        let lang = Sql::new();
+        assert_eq!(lang.binary(), "sqlite3")
+    }
+}
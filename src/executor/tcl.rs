@@ -0,0 +1,82 @@
+use std::path::Path;
+
+use crate::code_container::CodeContainer;
+
+use super::{script_arg, Executor};
+
+pub struct Tcl;
+
+impl Executor for Tcl {
+    fn export(&self, script: CodeContainer) -> String {
+        let mut header: Vec<String> = vec![
+            "#!/usr/bin/env tclsh".into(),
+            "".into(),
+        ];
+
+        header.push(script.lines());
+
+        header.join("\n")
+    }
+
+    fn binary(&self) -> &'static str {
+        "tclsh"
+    }
+
+    fn args(&self, script: Option<&Path>, args: Vec<String>) -> Vec<String> {
+        // Without a file, tclsh starts an interactive shell, and it can't
+        // pass arguments to a script from stdin.
+        let mut argv = vec![script_arg(script, "/dev/stdin")];
+        argv.extend(args);
+        argv
+    }
+
+    fn extension(&self) -> &'static str {
+        ".tcl"
+    }
+}
+
+impl Tcl {
+    pub fn new() -> Tcl {
+        Tcl{}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export() {
+        let mut code = CodeContainer::new();
+        code.open_new_group();
+        code.push("puts \"check\"".into());
+        code.close_group();
+
+        let lang = Tcl::new();
+        let output = lang.export(code);
+        let expected_output = "#!/usr/bin/env tclsh\n\nputs \"check\"".to_string();
+        assert_eq!(output, expected_output);
+    }
+
+    #[test]
+    fn test_args() {
+        let lang = Tcl::new();
+        let args = lang.args(None, vec!["--my-flag".into(), "-o".into(), "file".into()]);
+        let expected_args = vec!["/dev/stdin", "--my-flag", "-o", "file"];
+        assert_eq!(args, expected_args);
+    }
+
+    #[test]
+    fn test_file_args() {
+        let lang = Tcl::new();
+        let args = lang.args(Some(Path::new("/tmp/script.tcl")), vec!["--my-flag".into()]);
+        let expected_args = vec!["/tmp/script.tcl", "--my-flag"];
+        assert_eq!(args, expected_args);
+    }
+
+    #[test]
+    fn test_tcl_binary() {
+        let lang = Tcl::new();
+        assert_eq!(lang.binary(), "tclsh")
+    }
+}
@@ -0,0 +1,129 @@
+const DELIMITER: &str = "---";
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Value {
+    Scalar(String),
+    Map(Vec<(String, String)>),
+}
+
+/// Front matter of a Markdown file. Only a small subset of YAML is supported:
+/// top-level scalars and one level of maps.
+///
+/// ```yaml
+/// ---
+/// title: Install Flux
+/// env:
+///   GITHUB_USER: yitsushi
+/// ---
+/// ```
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct FrontMatter {
+    entries: Vec<(String, Value)>,
+}
+
+impl FrontMatter {
+    pub fn parse(source: &str) -> FrontMatter {
+        let mut lines = source.lines();
+        if lines.next().map(str::trim_end) != Some(DELIMITER) {
+            return FrontMatter::default()
+        }
+
+        let mut body = vec![];
+        for line in lines.by_ref() {
+            if line.trim_end() == DELIMITER {
+                return FrontMatter::from_lines(body)
+            }
+            body.push(line);
+        }
+
+        // Never closed, it's not a front matter.
+        FrontMatter::default()
+    }
+
+    fn from_lines(lines: Vec<&str>) -> FrontMatter {
+        let mut entries: Vec<(String, Value)> = vec![];
+
+        for line in lines {
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue
+            }
+
+            let Some((key, value)) = line.split_once(':') else {
+                continue
+            };
+            let (key, value) = (key.trim().to_string(), unquote(value.trim()));
+
+            if !line.starts_with([' ', '\t']) {
+                let value = if value.is_empty() {
+                    Value::Map(vec![])
+                } else {
+                    Value::Scalar(value)
+                };
+                entries.push((key, value));
+                continue
+            }
+
+            if let Some((_, Value::Map(map))) = entries.last_mut() {
+                map.push((key, value));
+            }
+        }
+
+        FrontMatter { entries }
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.entries.iter().find_map(|(k, v)| match v {
+            Value::Scalar(value) if k == key => Some(value.clone()),
+            _ => None,
+        })
+    }
+
+    pub fn map(&self, key: &str) -> Vec<(String, String)> {
+        self.entries.iter().find_map(|(k, v)| match v {
+            Value::Map(map) if k == key => Some(map.clone()),
+            _ => None,
+        }).unwrap_or_default()
+    }
+}
+
+fn unquote(value: &str) -> String {
+    for quote in ['"', '\''] {
+        if value.len() >= 2 && value.starts_with(quote) && value.ends_with(quote) {
+            return value[1..value.len() - 1].to_string()
+        }
+    }
+
+    value.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let source = "---\ntitle: Test\nenv:\n  A: 1\n  B: \"two words\"\nempty:\n---\n# Heading\n";
+        let fm = FrontMatter::parse(source);
+
+        assert_eq!(fm.entries[0], ("title".into(), Value::Scalar("Test".into())));
+        assert_eq!(fm.get("title"), Some("Test".into()));
+        assert_eq!(fm.get("env"), None);
+        assert_eq!(fm.get("missing"), None);
+        assert_eq!(fm.map("env"), vec![("A".into(), "1".into()), ("B".into(), "two words".into())]);
+        assert_eq!(fm.map("empty"), vec![]);
+        assert_eq!(fm.map("title"), vec![]);
+    }
+
+    #[test]
+    fn test_parse_without_front_matter() {
+        let test_cases: Vec<&str> = vec![
+            "# Heading\n---\nkey: value\n---\n",
+            "---\nkey: value\n",
+            "",
+        ];
+
+        for case in test_cases {
+            assert_eq!(FrontMatter::parse(case), FrontMatter::default());
+        }
+    }
+}
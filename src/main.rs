@@ -1,22 +1,50 @@
-use std::{io::{self, BufRead, BufReader, Lines, Write}, fs::File, path::Path};
+use std::{fs, io::{self, Write}, path::PathBuf, process::{ExitCode, ExitStatus}, time::Duration};
 
 mod executor;
+mod annotate;
 mod code_block_options;
 mod code_container;
-
-use clap::Parser;
+mod comment;
+mod config;
+mod diff;
+mod doctest;
+mod document;
+mod environment;
+mod error;
+mod front_matter;
+mod notebook;
+mod runner;
+mod tangle;
+mod timeout;
+
+use clap::{Parser, Subcommand};
 use code_block_options::{CodeBlockOption, find_group_name};
 use code_container::CodeContainer;
+use config::Config;
+use document::{Fence, Update};
+use error::Error;
+use executor::{Custom, Executor, Outcome, Registry};
+use notebook::ExportFormat;
+use runner::{describe_script, document_dir, has_block_timeout, Capture, Runner};
+
+/// Executor prefix for ad-hoc commands, for example 'sql:exec=sqlite3'.
+const EXEC_PREFIX: &str = "exec=";
 
 #[derive(Debug, Parser)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Language to extract.
     /// Executor can be defined with ':', for example:
     /// js:node or py:python.
-    language: String,
+    #[arg(required = true)]
+    language: Option<String>,
     /// Source file.
-    file: String,
+    #[arg(required = true)]
+    file: Option<String>,
     /// Arguments to the script.
     args: Vec<String>,
 
@@ -29,6 +57,16 @@ struct Args {
     #[arg(short, long)]
     export: bool,
 
+    /// Add a header with the source and the selection to the exported
+    /// script, and a comment before each block with its line and heading.
+    #[arg(long, requires = "export")]
+    annotate: bool,
+
+    /// Format of the export. A notebook has the whole document, the selected
+    /// blocks are code cells and the rest is in markdown cells.
+    #[arg(long, value_enum, default_value_t, value_name = "FORMAT", requires = "export")]
+    export_format: ExportFormat,
+
     /// Debug mode.
     #[arg(short, long)]
     debug: bool,
@@ -37,81 +75,352 @@ struct Args {
     /// or not.
     #[arg(short, long)]
     pick: bool,
+
+    /// Evaluate each code block as a separate script.
+    #[arg(long)]
+    each: bool,
+
+    /// Evaluate the code with an arbitrary command instead of a language,
+    /// for example: 'jq -f {file}'. With a {file} placeholder the code is
+    /// passed as a file, otherwise on stdin. Same as the 'exec=' executor,
+    /// for example: 'sql:exec=sqlite3 db.sqlite'.
+    #[arg(long, value_name = "COMMAND")]
+    exec: Option<String>,
+
+    /// Evaluate the code blocks one by one in the same interpreter, so the
+    /// state is kept between them (python, node, ruby and POSIX shells).
+    #[arg(long, conflicts_with = "each")]
+    session: bool,
+
+    /// Write the output of each code block back into the source file, into
+    /// an output block after the code block. Implies --each.
+    #[arg(long, conflicts_with_all = ["session", "export"])]
+    update: bool,
+
+    /// Tag of the output blocks.
+    #[arg(long, value_name = "TAG", default_value = "output", global = true)]
+    output_tag: String,
+
+    #[command(flatten)]
+    run: RunOptions,
 }
 
-fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
-where P: AsRef<Path>, {
-    let file = File::open(filename)?;
-    Ok(io::BufReader::new(file).lines())
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Evaluate the code blocks followed by an expected output block, and
+    /// compare their output with the expected one.
+    Test {
+        /// Source file.
+        file: String,
+
+        /// Write the actual output into the expected output blocks of the
+        /// failing code blocks.
+        #[arg(long)]
+        bless: bool,
+
+        /// Prompt of the commands in console blocks, besides "$ " and "❯ ".
+        /// Can be used multiple times.
+        #[arg(long, value_name = "PREFIX")]
+        prompt: Vec<String>,
+
+        /// Write a report of the results in junit, tap or json format, into
+        /// a file after ':' or to stdout, for example: junit:report.xml.
+        /// Can be used multiple times.
+        #[arg(long, value_name = "FORMAT[:FILE]", value_parser = doctest::report::parse_target)]
+        report: Vec<doctest::report::Target>,
+    },
+
+    /// Write the code blocks with a "file" option (or a mkdocs title) into
+    /// that file. Blocks of the same file are concatenated.
+    Tangle {
+        /// Source file.
+        file: String,
+
+        /// Directory of the relative paths.
+        /// [default: directory of the source file]
+        #[arg(long, value_name = "DIR")]
+        dir: Option<PathBuf>,
+
+        /// Don't write the origin markers, the comments before the blocks
+        /// that `detangle` needs.
+        #[arg(long)]
+        no_markers: bool,
+    },
+
+    /// Read the files written by `tangle` back into their code blocks, the
+    /// blocks are found by the origin markers.
+    Detangle {
+        /// Source file.
+        file: String,
+
+        /// Directory of the relative paths.
+        /// [default: directory of the source file]
+        #[arg(long, value_name = "DIR")]
+        dir: Option<PathBuf>,
+    },
 }
 
-fn main() {
+/// Settings of the evaluated scripts.
+#[derive(Debug, Default, clap::Args)]
+struct RunOptions {
+    /// Terminate the evaluation after the given time, for example: 30s, 5m.
+    /// Blocks can have their own limit with the "timeout" option.
+    #[arg(long, value_parser = timeout::parse_duration, global = true)]
+    timeout: Option<Duration>,
+
+    /// Set an environment variable for the script (KEY=VAL).
+    /// Can be used multiple times.
+    #[arg(long = "env", value_name = "KEY=VAL", value_parser = environment::parse_pair, global = true)]
+    env: Vec<(String, String)>,
+
+    /// Load environment variables from a file in dotenv format.
+    /// Can be used multiple times.
+    #[arg(long, value_name = "FILE", global = true)]
+    env_file: Vec<String>,
+
+    /// Start the script with an empty environment. Only basic variables
+    /// like PATH and HOME are kept, and the ones listed with --keep-env.
+    #[arg(long, global = true)]
+    clean_env: bool,
+
+    /// Keep the variable from the environment with --clean-env.
+    /// Can be used multiple times.
+    #[arg(long, value_name = "NAME", global = true)]
+    keep_env: Vec<String>,
+
+    /// Working directory of the script.
+    /// [default: directory of the source file]
+    #[arg(long, value_name = "DIR", global = true)]
+    cwd: Option<PathBuf>,
+
+    /// SQLite database of SQL code blocks.
+    /// [default: in-memory database]
+    #[arg(long, value_name = "FILE", global = true)]
+    db: Option<PathBuf>,
+
+    /// Python virtual environment of the script.
+    /// [default: .venv next to the source file, if it exists]
+    #[arg(long, value_name = "DIR", global = true)]
+    venv: Option<PathBuf>,
+
+    /// How the script is passed to the interpreter.
+    #[arg(long, value_enum, default_value_t, global = true)]
+    input: executor::Input,
+}
+
+fn main() -> ExitCode {
     let arguments: Args = Args::parse();
+    let (tag, file) = match &arguments.command {
+        Some(Command::Test { file, .. } | Command::Tangle { file, .. } | Command::Detangle { file, .. }) => {
+            (String::new(), file.clone())
+        },
+        None => (
+            extract_language(arguments.language.as_deref().unwrap_or_default()).0.to_string(),
+            arguments.file.clone().unwrap_or_default(),
+        ),
+    };
+
+    let registry = match Config::load(&document_dir(&file)) {
+        Ok(config) => Registry::new(config.languages.into_values().collect()),
+        Err(err) => {
+            report_error(&err, &tag, &Registry::default());
+            return ExitCode::from(err.exit_code())
+        },
+    };
+
+    let result = match &arguments.command {
+        Some(Command::Test { file, bless, prompt, report }) => {
+            doctest::run(file, *bless, prompt, report, &arguments.run, &arguments.output_tag, &registry)
+        },
+        Some(Command::Tangle { file, dir, no_markers }) => {
+            tangle::run(file, &dir.clone().unwrap_or_else(|| document_dir(file)), !no_markers)
+        },
+        Some(Command::Detangle { file, dir }) => {
+            tangle::detangle(file, &dir.clone().unwrap_or_else(|| document_dir(file)))
+        },
+        None => run(arguments, &registry),
+    };
+
+    match result {
+        Ok(code) => code,
+        Err(err) => {
+            report_error(&err, &tag, &registry);
+            ExitCode::from(err.exit_code())
+        },
+    }
+}
+
+fn run(arguments: Args, registry: &Registry) -> Result<ExitCode, Error> {
+    let language = arguments.language.unwrap_or_default();
+    let file = arguments.file.unwrap_or_default();
 
     if arguments.debug {
-        println!(" -- Target Language: {}", arguments.language);
-        println!(" -- Source file: {}", arguments.file);
+        println!(" -- Target Language: {}", language);
+        println!(" -- Source file: {}", file);
         println!(" -- Arguments: {:?}", arguments.args);
     }
 
-    let lines = match read_lines(arguments.file.clone()) {
-        Ok(it) => it,
-        _ => {
-            println!(" -- File not found: {}", arguments.file);
-            return
-        },
-    };
+    let source = fs::read_to_string(&file)
+        .map_err(|err| Error::io(format!("unable to read {}", file), err))?;
 
-    let (name, executor) = extract_language(arguments.language.as_str());
-    let content: CodeContainer = extract_content(name, lines, ExtractOptions {
-        group: arguments.group,
+    let (name, executor) = extract_language(language.as_str());
+    let content: CodeContainer = extract_content(name, &source, ExtractOptions {
+        group: arguments.group.clone(),
         pick: arguments.pick,
+        output_tag: Some(arguments.output_tag.clone()),
     });
-    let lang = executor::language_picker(executor);
+    let lang = match (arguments.exec.as_deref(), executor.strip_prefix(EXEC_PREFIX)) {
+        (Some(command), _) | (None, Some(command)) => Custom::from_command(name, command)
+            .map(|c| Box::new(c) as Box<dyn Executor>),
+        _ => registry.pick(name, executor),
+    };
 
     if arguments.pick {
         // Add an extra empty line to separate "pick" answers.
         eprintln!()
     }
 
-    let lang = if let Some(lang) = lang {
-        lang
-    } else {
-        let (supported, alias_list) = help_available();
-        println!(" -- unknown language: {}", arguments.language);
-        println!("available languages:\n{}\n", supported);
-        println!("aliases:\n{}", alias_list);
-
-        return
-    };
+    let lang = lang?;
 
     if arguments.debug {
         println!(" -- Target Language: {}", name);
         println!(" -- Target Executor: {}", executor);
     }
 
+    if arguments.export && arguments.export_format == ExportFormat::Ipynb {
+        if arguments.annotate {
+            return Err(Error::Unsupported("--annotate is not supported with notebooks".into()))
+        }
+        println!("{}", notebook::export(&source, &content, name, executor, lang.as_ref(), registry));
+
+        return Ok(ExitCode::SUCCESS)
+    }
+
+    if arguments.export && arguments.annotate {
+        let executor = match &arguments.exec {
+            Some(command) => format!("{}{}", EXEC_PREFIX, command),
+            None => executor.to_string(),
+        };
+        let selection = annotate::Selection {
+            file: &file,
+            tag: name,
+            executor: &executor,
+            group: arguments.group.as_deref(),
+            picked: arguments.pick,
+        };
+        println!("{}", annotate::export(lang.as_ref(), content, &selection, &document::headings(&source))?);
+
+        return Ok(ExitCode::SUCCESS)
+    }
+
     if arguments.export {
         println!("{}", lang.export(content));
 
-        return
+        return Ok(ExitCode::SUCCESS)
     }
 
     if arguments.debug {
         println!(" -- Target Binary: {}", lang.binary());
     }
 
-    let prog = lang.exec(content, arguments.args);
-    prog.wait_with_output().expect("Failed to read stdout");
+    let runner = Runner::new(&file, &source, &arguments.run, has_block_timeout(&content))?;
+
+    if arguments.session {
+        let mut session = lang.session(arguments.args.clone(), &runner.context(&content)?)?;
+
+        for block in content.split() {
+            let (limit, reported) = runner.limit(&block)?;
+            let target = describe_script(&file, &block);
+
+            println!(" -- {}", target);
+            match session.eval(&block.lines(), limit, &mut io::stdout())? {
+                Outcome::Done(0) => {},
+                Outcome::Done(status) => return Ok(ExitCode::from(status.clamp(1, 255) as u8)),
+                Outcome::Exited(status) => return Ok(exit_code(status)),
+                Outcome::TimedOut => return Err(Error::Timeout { target, limit: reported }),
+            }
+        }
+
+        return Ok(exit_code(session.finish()?))
+    }
+
+    let scripts = if arguments.each || arguments.update {
+        content.split()
+    } else {
+        vec![content]
+    };
+
+    let mut outputs: Vec<(usize, Update)> = vec![];
+    let mut result = Ok(ExitCode::SUCCESS);
+    for script in scripts {
+        let line = script.blocks().first().map(|block| block.line).unwrap_or_default();
+
+        let capture = if arguments.update { Capture::Combined } else { Capture::Terminal };
+        match runner.eval(lang.as_ref(), script, arguments.args.clone(), capture) {
+            Ok(evaluation) => {
+                outputs.extend(evaluation.output.map(|output| (line, Update::Output(output))));
+                if !evaluation.status.success() {
+                    result = Ok(exit_code(evaluation.status));
+                    break
+                }
+            },
+            Err(err) => {
+                result = Err(err);
+                break
+            },
+        }
+    }
+
+    // The output of a failed block is written too, it's part of the docs.
+    if arguments.update {
+        let is_output = |fence: &Fence| doctest::is_expectation(fence, &arguments.output_tag);
+        let updated = document::update_blocks(&source, &outputs, &is_output, &arguments.output_tag);
+        if updated != source {
+            fs::write(&file, updated)
+                .map_err(|err| Error::io(format!("unable to write {}", file), err))?;
+        }
+    }
+
+    result
 }
 
-fn help_available() -> (String, String) {
-    let supported = executor::supported_languages()
+/// Exit code of the evaluated script, scripts killed by a signal are reported
+/// as a generic failure.
+fn exit_code(status: ExitStatus) -> ExitCode {
+    match status.code() {
+        Some(code) => ExitCode::from(code as u8),
+        None => ExitCode::FAILURE,
+    }
+}
+
+fn report_error(err: &Error, tag: &str, registry: &Registry) {
+    eprintln!(" !! {}", err);
+
+    match err {
+        Error::UnknownLanguage(_) => {
+            let (supported, alias_list) = help_available(registry);
+            eprintln!("available languages:\n{}\n", supported);
+            eprintln!("aliases:\n{}", alias_list);
+        },
+        Error::InterpreterNotFound { binary, alternatives } => {
+            let mut hint = format!("install {}", binary);
+            for alternative in alternatives {
+                hint.push_str(&format!(" or use {}:{}", tag, alternative));
+            }
+            eprintln!(" -- hint: {}", hint);
+        },
+        _ => {},
+    }
+}
+
+fn help_available(registry: &Registry) -> (String, String) {
+    let supported = registry.supported_languages()
         .iter()
         .map(|l| format!(" - {}", l))
         .collect::<Vec<String>>()
         .join("\n");
 
-    let alias_list = executor::aliases()
+    let alias_list = registry.aliases()
         .iter()
         .map(|(a, n, e)| format!(" - {:<15} => {}::{}", a, n, e))
         .collect::<Vec<String>>()
@@ -123,48 +432,50 @@ fn help_available() -> (String, String) {
 struct ExtractOptions {
     group: Option<String>,
     pick: bool,
+    /// Tag of output blocks, they are never extracted with "all", like the
+    /// blocks with the "expect" option.
+    output_tag: Option<String>,
 }
 
-fn extract_content(name: &str, lines: Lines<BufReader<File>>, opts: ExtractOptions) -> CodeContainer {
-    let pattern = if name == "all" {
-        "```".to_string()
-    } else {
-        format!("```{}", name)
-    };
-    lines.into_iter().fold(CodeContainer::new(), |mut c, line| {
-        if let Ok(line) = line {
-            if line.starts_with(&pattern) {
-                if opts.group.is_none() {
-                    c.open_new_group();
-                    return c
-                }
-                let group = find_group_name(CodeBlockOption::parse_options(&line));
-                if group == opts.group.clone().unwrap_or_default() {
-                    c.open_new_group();
+fn extract_content(name: &str, source: &str, opts: ExtractOptions) -> CodeContainer {
+    let mut c = CodeContainer::new();
 
-                    return c
-                }
-            } else if line == "```" {
-                if let Some(block) = c.open_lines() {
-                    if !block.is_empty() {
-                        if opts.pick && !ask_yes_no(block) {
-                            c.discard();
-                            return c
-                        }
-
-                        c.close_group();
-                        return c
-                    }
-                }
+    for fence in document::fences(source) {
+        if !fence.closed || (name != "all" && !fence.info.trim_start_matches('`').starts_with(name)) {
+            continue
+        }
+        if name == "all" && opts.output_tag.as_ref().is_some_and(|tag| doctest::is_expectation(&fence, tag)) {
+            continue
+        }
 
-                c.discard()
-            } else if c.is_open() {
-                c.push(line);
-            }
+        let options = CodeBlockOption::parse_options(&fence.info);
+        let selected = match &opts.group {
+            Some(group) => find_group_name(options.clone()) == *group,
+            None => true,
+        };
+        if !selected {
+            continue
         }
 
-        c
-    })
+        c.open_block(fence.line, &fence.tag, options);
+        for line in fence.lines(source) {
+            c.push(line.to_string());
+        }
+        close_block(&mut c, &opts);
+    }
+
+    c
+}
+
+fn close_block(c: &mut CodeContainer, opts: &ExtractOptions) {
+    if let Some(block) = c.open_lines() {
+        if !block.is_empty() && (!opts.pick || ask_yes_no(block)) {
+            c.close_group();
+            return
+        }
+    }
+
+    c.discard()
 }
 
 fn extract_language(lang: &str) -> (&str, &str) {
@@ -204,6 +515,29 @@ fn ask_yes_no(block: String) -> bool {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_extract_content() {
+        let source = "# Doc\n\n```c\nint a;\n```\n\n```cpp\nint b;\n```\n\n```c #group=x\nint c;\n```\n\n```c\n```\n";
+
+        let content = extract_content("c", source, ExtractOptions::default());
+        assert_eq!(content.lines(), "int a;\nint b;\nint c;");
+        assert_eq!(content.blocks()[0].line, 3);
+        assert_eq!(content.blocks()[2].line, 11);
+
+        let content = extract_content("c", source, ExtractOptions { group: Some("x".into()), ..ExtractOptions::default() });
+        assert_eq!(content.lines(), "int c;");
+
+        let content = extract_content("c", source, ExtractOptions { group: Some("".into()), ..ExtractOptions::default() });
+        assert_eq!(content.lines(), "int a;\nint b;");
+
+        let content = extract_content("all", source, ExtractOptions::default());
+        assert_eq!(content.lines(), "int a;\nint b;\nint c;");
+
+        let source = "```c\nint a;\n```\n\n```output\nok\n```\n";
+        let content = extract_content("all", source, ExtractOptions { output_tag: Some("output".into()), ..ExtractOptions::default() });
+        assert_eq!(content.lines(), "int a;");
+    }
+
     #[test]
     fn test_extract_language() {
         let test_cases: Vec<(&str, &str, &str)> = vec![
@@ -0,0 +1,294 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::code_container::{Block, CodeContainer};
+use crate::document;
+use crate::executor::{Executor, Registry};
+
+const NBFORMAT: u32 = 4;
+const NBFORMAT_MINOR: u32 = 4;
+
+const FRONT_MATTER: &str = "---";
+
+/// Key of the block details in the cell metadata.
+const METADATA_KEY: &str = "eval-md";
+
+/// Format of `--export`.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, clap::ValueEnum)]
+pub enum ExportFormat {
+    /// The script of the selected blocks.
+    #[default]
+    Script,
+    /// A Jupyter notebook of the whole document.
+    Ipynb,
+}
+
+#[derive(Debug, Serialize)]
+struct Notebook {
+    metadata: Metadata,
+    nbformat: u32,
+    nbformat_minor: u32,
+    cells: Vec<Cell>,
+}
+
+#[derive(Debug, Serialize)]
+struct Metadata {
+    kernelspec: Kernelspec,
+    language_info: LanguageInfo,
+}
+
+#[derive(Debug, Serialize)]
+struct Kernelspec {
+    name: String,
+    display_name: String,
+    language: String,
+}
+
+#[derive(Debug, Serialize)]
+struct LanguageInfo {
+    name: String,
+    file_extension: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "cell_type", rename_all = "lowercase")]
+enum Cell {
+    Markdown {
+        metadata: Map<String, Value>,
+        source: Vec<String>,
+    },
+    Raw {
+        metadata: Map<String, Value>,
+        source: Vec<String>,
+    },
+    Code {
+        execution_count: Option<u32>,
+        metadata: Map<String, Value>,
+        outputs: Vec<Value>,
+        source: Vec<String>,
+    },
+}
+
+/// Language of the executor, for example "python" for `py:python3.12`.
+/// Languages of the configuration files are known too.
+fn language(registry: &Registry, tag: &str, executor: &str) -> String {
+    [executor, tag]
+        .into_iter()
+        .find_map(|name| registry.language(name))
+        .unwrap_or_else(|| tag.to_string())
+}
+
+/// Kernel of the language with its common name, for example `ir` for R.
+fn kernelspec(language: &str) -> Kernelspec {
+    let (name, display_name) = match language {
+        "python" => ("python3", "Python 3"),
+        "r" => ("ir", "R"),
+        "julia" => ("julia", "Julia"),
+        "javascript" => ("javascript", "JavaScript (Node.js)"),
+        "typescript" => ("tslab", "TypeScript"),
+        "ruby" => ("ruby", "Ruby"),
+        "shell" => ("bash", "Bash"),
+        "rust" => ("rust", "Rust"),
+        "go" => ("gophernotes", "Go"),
+        language => (language, language),
+    };
+
+    Kernelspec { name: name.into(), display_name: display_name.into(), language: language.into() }
+}
+
+/// Lines of a cell, all of them end with a newline except the last one.
+fn cell_source(text: &str) -> Vec<String> {
+    let text = text.trim_matches('\n');
+    let mut lines: Vec<String> = text.split_inclusive('\n').map(str::to_string).collect();
+    if let Some(last) = lines.last_mut() {
+        last.truncate(last.trim_end_matches('\n').len());
+    }
+
+    lines
+}
+
+/// Options of the block, options given more than once are arrays.
+fn block_metadata(block: &Block) -> Map<String, Value> {
+    let mut options = Map::new();
+    for option in &block.options {
+        let value = Value::String(option.value.clone());
+        match options.get_mut(&option.key) {
+            Some(Value::Array(values)) => values.push(value),
+            Some(existing) => *existing = Value::Array(vec![existing.take(), value]),
+            None => {
+                options.insert(option.key.clone(), value);
+            },
+        }
+    }
+
+    let mut details = Map::new();
+    details.insert("line".into(), block.line.into());
+    details.insert("tag".into(), block.tag.clone().into());
+    details.insert("options".into(), Value::Object(options));
+
+    let mut metadata = Map::new();
+    metadata.insert(METADATA_KEY.into(), Value::Object(details));
+
+    metadata
+}
+
+/// Byte offset after the front matter.
+fn front_matter_end(source: &str) -> Option<usize> {
+    let mut offset = 0;
+    for (index, line) in source.split_inclusive('\n').enumerate() {
+        offset += line.len();
+        match (index, line.trim_end() == FRONT_MATTER) {
+            (0, false) => return None,
+            (0, true) => {},
+            (_, true) => return Some(offset),
+            (_, false) => {},
+        }
+    }
+
+    None
+}
+
+fn markdown(cells: &mut Vec<Cell>, text: &str) {
+    if !text.trim().is_empty() {
+        cells.push(Cell::Markdown { metadata: Map::new(), source: cell_source(text) });
+    }
+}
+
+/// The document as a notebook: the selected blocks are code cells, the text
+/// between them is in markdown cells, and the front matter is a raw cell.
+pub fn export(
+    source: &str,
+    content: &CodeContainer,
+    tag: &str,
+    executor_name: &str,
+    executor: &dyn Executor,
+    registry: &Registry,
+) -> String {
+    let mut cells = vec![];
+    let mut copied = 0;
+
+    if let Some(end) = front_matter_end(source) {
+        cells.push(Cell::Raw { metadata: Map::new(), source: cell_source(&source[..end]) });
+        copied = end;
+    }
+
+    for fence in document::fences(source) {
+        let Some(block) = content.blocks().iter().find(|block| block.line == fence.line) else {
+            continue
+        };
+
+        markdown(&mut cells, &source[copied..fence.start]);
+        cells.push(Cell::Code {
+            execution_count: None,
+            metadata: block_metadata(block),
+            outputs: vec![],
+            source: cell_source(&block.lines.join("\n")),
+        });
+        copied = fence.end;
+    }
+    markdown(&mut cells, &source[copied..]);
+
+    let language = language(registry, tag, executor_name);
+    let notebook = Notebook {
+        metadata: Metadata {
+            kernelspec: kernelspec(&language),
+            language_info: LanguageInfo { name: language, file_extension: executor.extension().to_string() },
+        },
+        nbformat: NBFORMAT,
+        nbformat_minor: NBFORMAT_MINOR,
+        cells,
+    };
+
+    serde_json::to_string_pretty(&notebook).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::code_block_options::CodeBlockOption;
+    use crate::executor::{language_picker, Custom};
+
+    use super::*;
+
+    #[test]
+    fn test_language() {
+        let test_cases: Vec<(&str, &str, &str)> = vec![
+            ("python", "python", "python"),
+            ("py", "py", "python"),
+            ("py", "/opt/venv/bin/python", "python"),
+            ("bash", "bash", "shell"),
+            ("fennel", "fennel", "fennel"),
+            ("fnl", "fnl", "fennel"),
+            ("unknown", "unknown", "unknown"),
+        ];
+
+        let mut fennel = Custom::new("fennel", "fennel");
+        fennel.aliases = vec!["fnl".into()];
+        let registry = Registry::new(vec![fennel]);
+        for case in test_cases {
+            assert_eq!(language(&registry, case.0, case.1), case.2);
+        }
+    }
+
+    #[test]
+    fn test_cell_source() {
+        let test_cases: Vec<(&str, Vec<&str>)> = vec![
+            ("a\nb\n", vec!["a\n", "b"]),
+            ("\n\n# Title\n\ntext\n\n", vec!["# Title\n", "\n", "text"]),
+            ("single", vec!["single"]),
+            ("", vec![]),
+        ];
+
+        for case in test_cases {
+            assert_eq!(cell_source(case.0), case.1);
+        }
+    }
+
+    #[test]
+    fn test_front_matter_end() {
+        let test_cases: Vec<(&str, Option<usize>)> = vec![
+            ("---\ntitle: a\n---\n# Title\n", Some(17)),
+            ("# Title\n---\n", None),
+            ("---\ntitle: a\n", None),
+        ];
+
+        for case in test_cases {
+            assert_eq!(front_matter_end(case.0), case.1);
+        }
+    }
+
+    #[test]
+    fn test_export() {
+        let source = concat!(
+            "---\ntitle: Demo\n---\n",
+            "# Demo\n\nSetup:\n\n",
+            "```python #group=setup env=A=1 env=B=2\nimport os\n```\n",
+            "\n```bash\necho skipped\n```\n",
+            "```python\nprint(os.environ['A'])\n```\n",
+        );
+        let mut content = CodeContainer::new();
+        content.open_block(8, "python", CodeBlockOption::parse_options("```python #group=setup env=A=1 env=B=2"));
+        content.push("import os".into());
+        content.close_group();
+        content.open_block(15, "python", vec![]);
+        content.push("print(os.environ['A'])".into());
+        content.close_group();
+
+        let python = language_picker("python").unwrap();
+        let notebook: Value = serde_json::from_str(&export(source, &content, "python", "python", python.as_ref(), &Registry::default())).unwrap();
+
+        assert_eq!(notebook["nbformat"], 4);
+        assert_eq!(notebook["metadata"]["kernelspec"]["name"], "python3");
+        assert_eq!(notebook["metadata"]["language_info"]["file_extension"], ".py");
+
+        let cells = notebook["cells"].as_array().unwrap();
+        let types: Vec<&str> = cells.iter().map(|cell| cell["cell_type"].as_str().unwrap()).collect();
+        assert_eq!(types, vec!["raw", "markdown", "code", "markdown", "code"]);
+        assert_eq!(cells[1]["source"], serde_json::json!(["# Demo\n", "\n", "Setup:"]));
+        assert_eq!(cells[2]["source"], serde_json::json!(["import os"]));
+        assert_eq!(cells[2]["metadata"]["eval-md"]["line"], 8);
+        assert_eq!(cells[2]["metadata"]["eval-md"]["options"]["group"], "setup");
+        assert_eq!(cells[2]["metadata"]["eval-md"]["options"]["env"], serde_json::json!(["A=1", "B=2"]));
+        assert_eq!(cells[3]["source"], serde_json::json!(["```bash\n", "echo skipped\n", "```"]));
+        assert_eq!(cells[4]["outputs"], serde_json::json!([]));
+    }
+}
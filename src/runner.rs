@@ -0,0 +1,363 @@
+use std::{fs::File, io::{self, Read, Seek, SeekFrom}, path::{Path, PathBuf}, process::ExitStatus, sync::Arc, time::{Duration, Instant}};
+
+use crate::code_block_options::{CB_OPTION_CWD, CB_OPTION_ENV, CB_OPTION_TIMEOUT, CB_OPTION_VENV};
+use crate::code_container::CodeContainer;
+use crate::environment;
+use crate::error::Error;
+use crate::executor::{Context, Executor};
+use crate::front_matter::FrontMatter;
+use crate::timeout;
+use crate::RunOptions;
+
+/// Virtual environment next to the document, used by Python if it exists.
+const DEFAULT_VENV: &str = ".venv";
+
+/// Evaluates the scripts of a document with the settings from the command
+/// line, the front matter and the options of the code blocks.
+pub struct Runner {
+    ctx: Context,
+    file: String,
+    front_matter: FrontMatter,
+    cli_env: Vec<(String, String)>,
+    base_dir: PathBuf,
+    cli_venv: Option<PathBuf>,
+    venv_base: PathBuf,
+    timeout: Option<Duration>,
+    started: Instant,
+}
+
+impl Runner {
+    /// Scripts are started in their own process group if there is any time
+    /// limit, so they can be terminated together with their children.
+    pub fn new(file: &str, source: &str, options: &RunOptions, has_timeout: bool) -> Result<Runner, Error> {
+        // Variables from the command line override the ones in the document.
+        let mut cli_env = vec![];
+        for env_file in &options.env_file {
+            cli_env.extend(environment::read_env_file(env_file)?);
+        }
+        cli_env.extend(options.env.clone());
+
+        let mut keep_env: Vec<String> = environment::DEFAULT_ALLOWLIST.iter().map(|k| k.to_string()).collect();
+        keep_env.extend(options.keep_env.clone());
+
+        let ctx = Context {
+            process_group: options.timeout.is_some() || has_timeout,
+            clean_env: options.clean_env,
+            keep_env,
+            env: vec![],
+            cwd: None,
+            input: options.input,
+            // The script runs in another directory, so a relative path has to
+            // be resolved from the current one.
+            database: options.db.as_deref().map(std::path::absolute).transpose()
+                .map_err(|err| Error::io("invalid database path", err))?,
+            venv: None,
+            capture: None,
+            capture_stderr: None,
+            document: PathBuf::from(file),
+            deadline: None,
+            build_cache: None,
+        };
+
+        Ok(Runner {
+            ctx,
+            file: file.to_string(),
+            front_matter: FrontMatter::parse(source),
+            cli_env,
+            base_dir: match &options.cwd {
+                Some(dir) => dir.clone(),
+                None => document_dir(file),
+            },
+            cli_venv: options.venv.as_deref().map(std::path::absolute).transpose()
+                .map_err(|err| Error::io("invalid virtual environment path", err))?,
+            venv_base: std::path::absolute(document_dir(file))
+                .map_err(|err| Error::io("invalid document path", err))?,
+            timeout: options.timeout,
+            started: Instant::now(),
+        })
+    }
+
+    /// Context of a script, with the settings from the options of its blocks.
+    pub fn context(&self, script: &CodeContainer) -> Result<Context, Error> {
+        let mut ctx = self.ctx.clone();
+        ctx.env.extend(self.front_matter.map("env"));
+        ctx.env.extend(script_env(script)?);
+        ctx.env.extend(self.cli_env.clone());
+        ctx.cwd = Some(script_cwd(&self.base_dir, script)?);
+        ctx.venv = match &self.cli_venv {
+            Some(venv) => Some(venv.clone()),
+            None => script_venv(&self.venv_base, &self.front_matter, script),
+        };
+
+        Ok(ctx)
+    }
+
+    /// Time limit of a script, and the limit to report on timeout.
+    pub fn limit(&self, script: &CodeContainer) -> Result<(Option<Duration>, Duration), Error> {
+        let remaining = self.timeout.map(|t| t.saturating_sub(self.started.elapsed()));
+
+        Ok(time_limit(script_timeout(script)?, remaining, self.timeout))
+    }
+
+    /// Evaluates the script and waits for it, its output is captured as
+    /// requested.
+    pub fn eval(&self, lang: &dyn Executor, script: CodeContainer, argv: Vec<String>, capture: Capture) -> Result<Evaluation, Error> {
+        let (limit, reported) = self.limit(&script)?;
+        let target = describe_script(&self.file, &script);
+        let mut ctx = self.context(&script)?;
+        ctx.deadline = limit.map(|limit| Instant::now() + limit);
+        if capture != Capture::Terminal {
+            ctx.capture = Some(Arc::new(capture_file()?));
+        }
+        if capture == Capture::Separate {
+            ctx.capture_stderr = Some(Arc::new(capture_file()?));
+        }
+
+        // A build before the script takes from its time.
+        let mut prog = lang.exec(script, argv, &ctx).map_err(|err| match err {
+            Error::Timeout { .. } => Error::Timeout { target: target.clone(), limit: reported },
+            err => err,
+        })?;
+        let limit = ctx.deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        let status = timeout::wait(&mut prog.child, limit)
+            .map_err(|err| Error::io(format!("failed to wait for {}", lang.binary()), err))?;
+
+        let Some(status) = status else {
+            return Err(Error::Timeout { target, limit: reported })
+        };
+
+        Ok(Evaluation {
+            status,
+            output: ctx.capture.as_deref().map(read_capture).transpose()?,
+            stderr: ctx.capture_stderr.as_deref().map(read_capture).transpose()?,
+        })
+    }
+}
+
+/// Where the output of a script goes.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Capture {
+    /// Printed, nothing is captured.
+    Terminal,
+    /// Stdout and stderr together, in the order they were written.
+    Combined,
+    /// Stdout and stderr on their own.
+    Separate,
+}
+
+/// Result of an evaluated script.
+#[derive(Debug)]
+pub struct Evaluation {
+    pub status: ExitStatus,
+    /// Captured stdout, with stderr too if it's not captured on its own.
+    pub output: Option<String>,
+    pub stderr: Option<String>,
+}
+
+fn capture_file() -> Result<File, Error> {
+    tempfile::tempfile().map_err(|err| Error::io("unable to create temporary output file", err))
+}
+
+/// Output of a script captured into a file.
+fn read_capture(mut file: &File) -> Result<String, Error> {
+    let mut output = vec![];
+
+    file.seek(SeekFrom::Start(0))
+        .and_then(|_| file.read_to_end(&mut output))
+        .map_err(|err| Error::io("unable to read the output", err))?;
+
+    Ok(String::from_utf8_lossy(&output).to_string())
+}
+
+/// Time limit of a script from its own limit and the remaining time of the
+/// global one, and the limit to report on timeout. The reported limit is the
+/// one the user set, not the remaining time.
+fn time_limit(script: Option<Duration>, remaining: Option<Duration>, global: Option<Duration>) -> (Option<Duration>, Duration) {
+    match (script, remaining) {
+        (Some(script), Some(remaining)) if script <= remaining => (Some(script), script),
+        (_, Some(remaining)) => (Some(remaining), global.unwrap_or_default()),
+        (script, None) => (script, script.unwrap_or_default()),
+    }
+}
+
+pub fn has_block_timeout(script: &CodeContainer) -> bool {
+    script.blocks().iter().any(|b| b.option(CB_OPTION_TIMEOUT).is_some())
+}
+
+/// Time limit of a script based on the "timeout" option of its blocks. If a
+/// script has more than one block, their limits are added up. A limit on only
+/// some of the blocks is an error, it could not be applied to them.
+fn script_timeout(script: &CodeContainer) -> Result<Option<Duration>, Error> {
+    let mut total = Duration::ZERO;
+    let mut limited = vec![];
+
+    for block in script.blocks() {
+        if let Some(value) = block.option(CB_OPTION_TIMEOUT) {
+            total += timeout::parse_duration(&value)?;
+            limited.push(block.line.to_string());
+        }
+    }
+
+    if !limited.is_empty() && limited.len() != script.blocks().len() {
+        return Err(Error::Parse(format!(
+            "only some blocks have a timeout (lines {}), use --each to limit them one by one",
+            limited.join(", "),
+        )))
+    }
+
+    Ok(Some(total).filter(|t| !t.is_zero()))
+}
+
+/// Environment variables defined with the "env" option on the blocks.
+fn script_env(script: &CodeContainer) -> Result<Vec<(String, String)>, Error> {
+    script.blocks()
+        .iter()
+        .flat_map(|block| block.option_values(CB_OPTION_ENV))
+        .map(|pair| environment::parse_pair(&pair))
+        .collect()
+}
+
+/// Python virtual environment of a script from the "venv" option of the
+/// blocks, or from the front matter. Relative paths are resolved from the
+/// directory of the document, where `.venv` is used if it exists.
+fn script_venv(document_dir: &Path, front_matter: &FrontMatter, script: &CodeContainer) -> Option<PathBuf> {
+    let venv = script.blocks()
+        .iter()
+        .find_map(|block| block.option(CB_OPTION_VENV))
+        .or_else(|| front_matter.get("venv"));
+
+    match venv {
+        Some(venv) => Some(document_dir.join(venv)),
+        None => Some(document_dir.join(DEFAULT_VENV)).filter(|dir| dir.is_dir()),
+    }
+}
+
+/// Directory of the source file, scripts are evaluated there by default, so
+/// relative paths in the document work from anywhere.
+pub fn document_dir(file: &str) -> PathBuf {
+    match Path::new(file).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// Working directory of a script. Blocks can change it with the "cwd" option,
+/// relative paths are resolved from the base directory.
+fn script_cwd(base: &Path, script: &CodeContainer) -> Result<PathBuf, Error> {
+    let mut dirs = script.blocks()
+        .iter()
+        .filter_map(|block| block.option(CB_OPTION_CWD))
+        .collect::<Vec<String>>();
+    dirs.dedup();
+
+    let dir = match dirs.as_slice() {
+        [] => base.to_path_buf(),
+        [dir] => base.join(dir),
+        _ => return Err(Error::Parse(format!(
+            "blocks have different working directories ({}), use --each to evaluate them separately",
+            dirs.join(", "),
+        ))),
+    };
+
+    if !dir.is_dir() {
+        return Err(Error::io(
+            format!("invalid working directory {}", dir.display()),
+            io::ErrorKind::NotFound.into(),
+        ))
+    }
+
+    Ok(dir)
+}
+
+pub fn describe_script(file: &str, script: &CodeContainer) -> String {
+    match script.blocks() {
+        [block] => format!("block at {}:{}", file, block.line),
+        blocks => format!("script of {} blocks from {}", blocks.len(), file),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::code_block_options::CodeBlockOption;
+
+    #[test]
+    fn test_document_dir() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            ("example/test.md", "example"),
+            ("/tmp/docs/test.md", "/tmp/docs"),
+            ("test.md", "."),
+        ];
+
+        for case in test_cases {
+            assert_eq!(document_dir(case.0), PathBuf::from(case.1));
+        }
+    }
+
+    #[test]
+    fn test_script_cwd() {
+        let mut code = CodeContainer::new();
+        code.open_block(1, "", vec![]);
+        code.push("pwd".into());
+        code.close_group();
+        code.open_block(5, "bash", CodeBlockOption::parse_options("```bash #cwd=executor"));
+        code.push("pwd".into());
+        code.close_group();
+
+        let base = Path::new("src");
+        assert_eq!(script_cwd(base, &code).ok(), Some(PathBuf::from("src/executor")));
+
+        let parts = code.clone().split();
+        assert_eq!(script_cwd(base, &parts[0]).ok(), Some(PathBuf::from("src")));
+
+        code.open_block(9, "bash", CodeBlockOption::parse_options("```bash #cwd=.."));
+        code.push("pwd".into());
+        code.close_group();
+        assert!(script_cwd(base, &code).is_err());
+    }
+
+    #[test]
+    fn test_script_timeout() {
+        let block = |code: &mut CodeContainer, line: usize, info: &str| {
+            code.open_block(line, "bash", CodeBlockOption::parse_options(info));
+            code.push("sleep 1".into());
+            code.close_group();
+        };
+
+        let mut code = CodeContainer::new();
+        block(&mut code, 1, "```bash #timeout=1s");
+        block(&mut code, 5, "```bash #timeout=2s");
+        assert_eq!(script_timeout(&code).ok(), Some(Some(Duration::from_secs(3))));
+
+        block(&mut code, 9, "```bash");
+        assert!(script_timeout(&code).is_err());
+
+        let parts = code.split();
+        assert_eq!(script_timeout(&parts[0]).ok(), Some(Some(Duration::from_secs(1))));
+        assert_eq!(script_timeout(&parts[2]).ok(), Some(None));
+    }
+
+    #[test]
+    fn test_script_venv() {
+        let mut code = CodeContainer::new();
+        code.open_block(1, "python", vec![]);
+        code.push("import sys".into());
+        code.close_group();
+
+        let dir = tempfile::tempdir().unwrap();
+        let front_matter = FrontMatter::parse("---\nvenv: envs/docs\n---\n");
+
+        assert_eq!(script_venv(dir.path(), &FrontMatter::default(), &code), None);
+        assert_eq!(script_venv(dir.path(), &front_matter, &code), Some(dir.path().join("envs/docs")));
+
+        fs::create_dir(dir.path().join(".venv")).unwrap();
+        assert_eq!(script_venv(dir.path(), &FrontMatter::default(), &code), Some(dir.path().join(".venv")));
+
+        code.open_block(5, "python", CodeBlockOption::parse_options("```python #venv=/opt/venv"));
+        code.push("print(sys.prefix)".into());
+        code.close_group();
+        assert_eq!(script_venv(dir.path(), &front_matter, &code), Some(PathBuf::from("/opt/venv")));
+    }
+}
@@ -0,0 +1,402 @@
+use std::{fs, path::{Component, Path, PathBuf}, process::ExitCode};
+
+use crate::code_block_options::{CodeBlockOption, CB_OPTION_FILE, CB_OPTION_MODE, find_option, split_words};
+use crate::comment::Comment;
+use crate::document::{self, Fence, Update};
+use crate::error::Error;
+
+/// Attribute of the fence with the file name in mkdocs, for example
+/// ```` ```py title="app.py" ````.
+const TITLE_ATTRIBUTE: &str = "title=";
+
+/// Text of the origin markers before the blocks, followed by the document and
+/// the line of the block, for example `# eval-md:doc.md:12`.
+const MARKER_PREFIX: &str = "eval-md:";
+
+const SHEBANG: &str = "#!";
+
+/// A file written from the code blocks.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Target {
+    /// Path from the "file" option, relative to the output directory.
+    pub path: PathBuf,
+    /// Permissions from the "mode" option of the first block that has one.
+    pub mode: Option<u32>,
+    /// Code blocks in the order of the document.
+    pub blocks: Vec<Fence>,
+}
+
+/// Path of the file from the "file" option, or from the mkdocs title.
+fn target_path(fence: &Fence) -> Option<String> {
+    if let Some(path) = find_option(&CodeBlockOption::parse_options(&fence.info), CB_OPTION_FILE) {
+        return Some(path).filter(|path| !path.is_empty())
+    }
+
+    let attributes = fence.info.split('#').next().unwrap_or_default();
+    split_words(attributes.trim_start_matches('`'))
+        .into_iter()
+        .find_map(|word| word.strip_prefix(TITLE_ATTRIBUTE).map(str::to_string))
+        .filter(|path| !path.is_empty())
+}
+
+/// True if the path stays in the output directory: it's relative and has no
+/// `..` components.
+fn is_contained(path: &Path) -> bool {
+    path.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+/// Parses octal file modes like `0755` or `644`.
+pub fn parse_mode(value: &str) -> Result<u32, Error> {
+    u32::from_str_radix(value.trim_start_matches("0o"), 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777)
+        .ok_or_else(|| Error::Parse(format!("invalid file mode: {:?}", value)))
+}
+
+/// Files of the document, blocks with the same path are concatenated.
+pub fn targets(source: &str) -> Result<Vec<Target>, Error> {
+    let mut targets: Vec<Target> = vec![];
+
+    for fence in document::fences(source).into_iter().filter(|fence| fence.closed) {
+        let Some(path) = target_path(&fence) else {
+            continue
+        };
+        let mode = find_option(&CodeBlockOption::parse_options(&fence.info), CB_OPTION_MODE)
+            .map(|mode| parse_mode(&mode))
+            .transpose()?;
+
+        let path = PathBuf::from(path);
+        if !is_contained(&path) {
+            return Err(Error::Parse(format!(
+                "file of the block at line {} is outside of the output directory: {}",
+                fence.line,
+                path.display(),
+            )))
+        }
+        match targets.iter_mut().find(|target| target.path == path) {
+            Some(target) => {
+                target.mode = target.mode.or(mode);
+                target.blocks.push(fence);
+            },
+            None => targets.push(Target { path, mode, blocks: vec![fence] }),
+        }
+    }
+
+    Ok(targets)
+}
+
+/// Origin markers of the blocks of a file, in the comment syntax of the
+/// language of its first block.
+#[derive(Debug, Clone)]
+pub struct Markers<'a> {
+    /// File name of the document.
+    pub document: &'a str,
+    pub comment: Comment,
+}
+
+impl<'a> Markers<'a> {
+    /// None if the language of the file has no comments.
+    pub fn new(document: &'a str, target: &Target) -> Option<Markers<'a>> {
+        let comment = Comment::for_tag(&target.blocks.first()?.tag)?;
+
+        Some(Markers { document, comment })
+    }
+
+    fn line(&self, line: usize) -> String {
+        self.comment.line(&format!("{}{}:{}", MARKER_PREFIX, self.document, line))
+    }
+
+    /// Document and line of a marker.
+    fn parse<'l>(&self, line: &'l str) -> Option<(&'l str, usize)> {
+        let (document, line) = self.comment.text(line)?.strip_prefix(MARKER_PREFIX)?.rsplit_once(':')?;
+
+        Some((document, line.parse().ok()?))
+    }
+}
+
+/// File name of the document in the markers.
+fn document_name(file: &str) -> String {
+    Path::new(file)
+        .file_name()
+        .map_or_else(|| file.to_string(), |name| name.to_string_lossy().into_owned())
+}
+
+/// Content of the file, each block follows its marker. A shebang stays in the
+/// first line.
+pub fn render(source: &str, target: &Target, markers: Option<&Markers>) -> String {
+    let mut content = String::new();
+
+    for (index, fence) in target.blocks.iter().enumerate() {
+        let mut lines = fence.lines(source).peekable();
+        if index == 0 {
+            if let Some(shebang) = lines.next_if(|line| line.starts_with(SHEBANG)) {
+                content.push_str(&format!("{}\n", shebang));
+            }
+        }
+        if let Some(markers) = markers {
+            content.push_str(&format!("{}\n", markers.line(fence.line)));
+        }
+        content.extend(lines.map(|line| format!("{}\n", line)));
+    }
+
+    content
+}
+
+/// New bodies of the blocks of the file from its tangled content. The
+/// markers must be the ones of the blocks, in the same order.
+pub fn split(content: &str, target: &Target, markers: &Markers) -> Result<Vec<String>, Error> {
+    let path = target.path.display();
+    let mut found: Vec<usize> = vec![];
+    let mut bodies: Vec<String> = vec![];
+    let mut before = String::new();
+
+    for (index, line) in content.lines().enumerate() {
+        match markers.parse(line) {
+            Some((document, _)) if document != markers.document => {
+                return Err(Error::Parse(format!("{}:{}: marker of another document: {}", path, index + 1, document)))
+            },
+            Some((_, line)) => {
+                found.push(line);
+                bodies.push(String::new());
+            },
+            None => {
+                let body = bodies.last_mut().unwrap_or(&mut before);
+                body.push_str(line);
+                body.push('\n');
+            },
+        }
+    }
+
+    let expected: Vec<usize> = target.blocks.iter().map(|fence| fence.line).collect();
+    if found != expected {
+        return Err(Error::Parse(format!(
+            "{}: the markers don't match the blocks of {}, expected lines {:?}, found {:?}",
+            path, markers.document, expected, found,
+        )))
+    }
+
+    match before.lines().collect::<Vec<_>>().as_slice() {
+        [] => {},
+        [shebang] if shebang.starts_with(SHEBANG) => bodies[0].insert_str(0, &before),
+        _ => return Err(Error::Parse(format!("{}: text before the first marker", path))),
+    }
+
+    for (fence, body) in target.blocks.iter().zip(&bodies) {
+        let closing = "`".repeat(fence.ticks);
+        if body.lines().any(|line| line.trim_start().starts_with(&closing)) {
+            return Err(Error::Parse(format!(
+                "{}: the block of line {} would be closed by a line of backticks",
+                path, fence.line,
+            )))
+        }
+    }
+
+    Ok(bodies)
+}
+
+/// Writes the content of the file, parent directories are created.
+fn write(path: &Path, content: &str, mode: Option<u32>) -> Result<(), Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|err| Error::io(format!("unable to create {}", parent.display()), err))?;
+    }
+
+    fs::write(path, content)
+        .map_err(|err| Error::io(format!("unable to write {}", path.display()), err))?;
+    if let Some(mode) = mode {
+        set_mode(path, mode)?;
+    }
+
+    Ok(())
+}
+
+/// Writes the files of the document into the directory, with origin markers
+/// unless they are disabled.
+pub fn run(file: &str, dir: &Path, with_markers: bool) -> Result<ExitCode, Error> {
+    let source = fs::read_to_string(file)
+        .map_err(|err| Error::io(format!("unable to read {}", file), err))?;
+    let name = document_name(file);
+
+    for target in targets(&source)? {
+        let markers = Markers::new(&name, &target).filter(|_| with_markers);
+        let path = dir.join(&target.path);
+        write(&path, &render(&source, &target, markers.as_ref()), target.mode)?;
+
+        let blocks = target.blocks.len();
+        println!(" -- {} ({} block{})", path.display(), blocks, if blocks == 1 { "" } else { "s" });
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Reads the tangled files back into the code blocks of the document. Files
+/// in languages without comments have no markers, and they are skipped. The
+/// markers of the files are refreshed, as the lines of the blocks can change.
+pub fn detangle(file: &str, dir: &Path) -> Result<ExitCode, Error> {
+    let source = fs::read_to_string(file)
+        .map_err(|err| Error::io(format!("unable to read {}", file), err))?;
+    let name = document_name(file);
+
+    // All files are checked before the document is written.
+    let mut updates = vec![];
+    for target in targets(&source)? {
+        let path = dir.join(&target.path);
+        let Some(markers) = Markers::new(&name, &target) else {
+            println!(" -- {} skipped, {} has no comments for the markers", path.display(), target.blocks[0].tag);
+            continue
+        };
+
+        let content = fs::read_to_string(&path)
+            .map_err(|err| Error::io(format!("unable to read {}", path.display()), err))?;
+        for (fence, body) in target.blocks.iter().zip(split(&content, &target, &markers)?) {
+            if source[fence.body.clone()] != body {
+                updates.push((fence.line, Update::Body(body)));
+            }
+        }
+    }
+
+    if updates.is_empty() {
+        println!(" -- {} is up to date", file);
+        return Ok(ExitCode::SUCCESS)
+    }
+
+    let updated = document::update_blocks(&source, &updates, &|_| false, "");
+    fs::write(file, &updated)
+        .map_err(|err| Error::io(format!("unable to write {}", file), err))?;
+    let blocks = updates.len();
+    println!(" -- {} ({} block{} updated)", file, blocks, if blocks == 1 { "" } else { "s" });
+
+    for target in targets(&updated)? {
+        let Some(markers) = Markers::new(&name, &target) else {
+            continue
+        };
+        let path = dir.join(&target.path);
+        let content = render(&updated, &target, Some(&markers));
+        if fs::read_to_string(&path).is_ok_and(|existing| existing != content) {
+            write(&path, &content, None)?;
+            println!(" -- {} (markers updated)", path.display());
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> Result<(), Error> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+        .map_err(|err| Error::io(format!("unable to set the mode of {}", path.display()), err))
+}
+
+/// File modes are not supported, the file is kept as it is.
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> Result<(), Error> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_targets() {
+        let source = concat!(
+            "```bash #file=bin/run.sh mode=0755\n#!/bin/sh\n```\n",
+            "```bash\necho skipped\n```\n",
+            "``` py title=\"app/main.py\"\nprint(1)\n```\n",
+            "```bash #file=bin/run.sh mode=0644\necho run\n```\n",
+        );
+        let targets = targets(source).unwrap();
+
+        assert_eq!(targets.len(), 2);
+        assert_eq!((targets[0].path.as_path(), targets[0].mode), (Path::new("bin/run.sh"), Some(0o755)));
+        assert_eq!(render(source, &targets[0], None), "#!/bin/sh\necho run\n");
+        assert_eq!((targets[1].path.as_path(), targets[1].mode), (Path::new("app/main.py"), None));
+        assert_eq!(render(source, &targets[1], None), "print(1)\n");
+
+        assert!(super::targets("```bash #file=x mode=999\necho\n```\n").is_err());
+    }
+
+    #[test]
+    fn test_targets_outside() {
+        let test_cases: Vec<(&str, bool)> = vec![
+            ("```bash #file=./bin/run.sh\necho\n```\n", true),
+            ("```bash #file=/etc/profile\necho\n```\n", false),
+            ("```bash #file=bin/../../run.sh\necho\n```\n", false),
+            ("\n``` py title=\"../app.py\"\nprint(1)\n```\n", false),
+        ];
+
+        for case in test_cases {
+            assert_eq!(targets(case.0).is_ok(), case.1, "{}", case.0);
+        }
+
+        let err = targets("\n``` py title=\"../app.py\"\nprint(1)\n```\n").unwrap_err();
+        assert!(err.to_string().contains("line 2"));
+    }
+
+    #[test]
+    fn test_render_markers() {
+        let source = concat!(
+            "```bash #file=run.sh\n#!/bin/sh\nset -e\n```\n",
+            "```bash #file=run.sh\necho run\n```\n",
+            "```json #file=config.json\n{}\n```\n",
+        );
+        let targets = targets(source).unwrap();
+
+        let markers = Markers::new("doc.md", &targets[0]);
+        assert_eq!(
+            render(source, &targets[0], markers.as_ref()),
+            "#!/bin/sh\n# eval-md:doc.md:1\nset -e\n# eval-md:doc.md:5\necho run\n",
+        );
+        assert!(Markers::new("doc.md", &targets[1]).is_none());
+    }
+
+    #[test]
+    fn test_split() {
+        let source = concat!(
+            "```bash #file=run.sh\n#!/bin/sh\nset -e\n```\n",
+            "```bash #file=run.sh\necho run\n```\n",
+        );
+        let targets = targets(source).unwrap();
+        let markers = Markers::new("doc.md", &targets[0]).unwrap();
+
+        let test_cases: Vec<(&str, Option<Vec<&str>>)> = vec![
+            (
+                "#!/bin/sh\n# eval-md:doc.md:1\nset -eu\n# eval-md:doc.md:5\necho run\necho done\n",
+                Some(vec!["#!/bin/sh\nset -eu\n", "echo run\necho done\n"]),
+            ),
+            ("# eval-md:doc.md:1\n# eval-md:doc.md:5\n", Some(vec!["", ""])),
+            // A marker is missing.
+            ("# eval-md:doc.md:1\nset -e\necho run\n", None),
+            // A marker is repeated.
+            ("# eval-md:doc.md:1\n# eval-md:doc.md:1\n# eval-md:doc.md:5\n", None),
+            // The marker of another document.
+            ("# eval-md:doc.md:1\n# eval-md:other.md:5\n", None),
+            ("echo before\n# eval-md:doc.md:1\n# eval-md:doc.md:5\n", None),
+            ("# eval-md:doc.md:1\n```\n# eval-md:doc.md:5\n", None),
+        ];
+
+        for case in test_cases {
+            let bodies = split(case.0, &targets[0], &markers).ok();
+            assert_eq!(bodies, case.1.map(|bodies| bodies.into_iter().map(str::to_string).collect()));
+        }
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        let test_cases: Vec<(&str, Option<u32>)> = vec![
+            ("0755", Some(0o755)),
+            ("644", Some(0o644)),
+            ("0o600", Some(0o600)),
+            ("4755", Some(0o4755)),
+            ("0855", None),
+            ("rwx", None),
+            ("17777", None),
+        ];
+
+        for case in test_cases {
+            assert_eq!(parse_mode(case.0).ok(), case.1);
+        }
+    }
+}
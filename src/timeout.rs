@@ -0,0 +1,159 @@
+use std::{
+    io,
+    process::{Child, ExitStatus},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::error::Error;
+
+/// Time between the polite termination request and the forced kill.
+const GRACE_PERIOD: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Parses durations like `500ms`, `30s`, `5m` or `1h`. Values without unit are
+/// seconds.
+pub fn parse_duration(value: &str) -> Result<Duration, Error> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let number: u64 = number
+        .parse()
+        .map_err(|_| Error::Parse(format!("invalid duration: {:?}", value)))?;
+
+    match unit {
+        "ms" => Ok(Duration::from_millis(number)),
+        "" | "s" => Ok(Duration::from_secs(number)),
+        "m" => Ok(Duration::from_secs(number * 60)),
+        "h" => Ok(Duration::from_secs(number * 60 * 60)),
+        _ => Err(Error::Parse(format!("invalid duration unit {:?} in {:?}", unit, value))),
+    }
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+
+    if duration.subsec_millis() != 0 || secs == 0 {
+        format!("{}ms", duration.as_millis())
+    } else if secs.is_multiple_of(3600) {
+        format!("{}h", secs / 3600)
+    } else if secs.is_multiple_of(60) {
+        format!("{}m", secs / 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+/// Waits for the child to exit. If the limit is reached before that, the whole
+/// process group is terminated and `None` is returned.
+pub fn wait(child: &mut Child, limit: Option<Duration>) -> io::Result<Option<ExitStatus>> {
+    let limit = match limit {
+        Some(limit) => limit,
+        None => return child.wait().map(Some),
+    };
+
+    if wait_until(child, Instant::now() + limit)?.is_some() {
+        return child.wait().map(Some)
+    }
+
+    terminate(child)?;
+
+    Ok(None)
+}
+
+fn wait_until(child: &mut Child, deadline: Instant) -> io::Result<Option<ExitStatus>> {
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status))
+        }
+
+        if Instant::now() >= deadline {
+            return Ok(None)
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(unix)]
+pub fn terminate(child: &mut Child) -> io::Result<()> {
+    let group = -(child.id() as libc::pid_t);
+
+    // Processes of the group can still be alive even after the main process
+    // exited, signal the whole group regardless.
+    unsafe { libc::kill(group, libc::SIGTERM) };
+    let status = wait_until(child, Instant::now() + GRACE_PERIOD)?;
+    unsafe { libc::kill(group, libc::SIGKILL) };
+
+    if status.is_none() {
+        child.wait()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn terminate(child: &mut Child) -> io::Result<()> {
+    child.kill()?;
+    child.wait()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        let test_cases: Vec<(&str, Option<Duration>)> = vec![
+            ("500ms", Some(Duration::from_millis(500))),
+            ("30s", Some(Duration::from_secs(30))),
+            ("30", Some(Duration::from_secs(30))),
+            ("5m", Some(Duration::from_secs(300))),
+            ("2h", Some(Duration::from_secs(7200))),
+            ("5d", None),
+            ("m", None),
+            ("", None),
+        ];
+
+        for case in test_cases {
+            assert_eq!(parse_duration(case.0).ok(), case.1);
+        }
+    }
+
+    #[test]
+    fn test_format_duration() {
+        let test_cases: Vec<(Duration, &str)> = vec![
+            (Duration::from_millis(500), "500ms"),
+            (Duration::from_secs(30), "30s"),
+            (Duration::from_secs(300), "5m"),
+            (Duration::from_secs(7200), "2h"),
+            (Duration::from_secs(90), "90s"),
+        ];
+
+        for case in test_cases {
+            assert_eq!(format_duration(case.0), case.1);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_wait_kills_process_group() {
+        use std::os::unix::process::CommandExt;
+        use std::process::Command;
+
+        let mut child = Command::new("sh")
+            .args(["-c", "sleep 10 & sleep 10"])
+            .process_group(0)
+            .spawn()
+            .unwrap();
+
+        let start = Instant::now();
+        let status = wait(&mut child, Some(Duration::from_millis(100))).unwrap();
+
+        assert!(status.is_none());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}